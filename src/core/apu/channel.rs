@@ -0,0 +1,122 @@
+use core::state::*;
+
+// Units shared between the sound channels
+
+// Counts down at 256Hz and silences the channel when it expires
+pub struct LengthCounter {
+	pub enabled: bool,
+	counter: u16,
+	max: u16,
+}
+
+impl LengthCounter {
+	pub fn new(max: u16) -> LengthCounter {
+		LengthCounter {
+			enabled: false,
+			counter: 0,
+			max: max,
+		}
+	}
+
+	// Games write the length as (max - length)
+	pub fn load(&mut self, length: u16) {
+		self.counter = self.max - length;
+	}
+
+	pub fn trigger(&mut self) {
+		if self.counter == 0 {
+			self.counter = self.max;
+		}
+	}
+
+	// Clocked by the frame sequencer. Returns false once the channel should be disabled
+	pub fn clock(&mut self) -> bool {
+		if self.enabled && self.counter > 0 {
+			self.counter -= 1;
+			return self.counter != 0;
+		}
+		true
+	}
+}
+
+// Volume envelope for the square and noise channels, clocked at 64Hz
+pub struct Envelope {
+	initial_volume: u8,
+	increase: bool,
+	period: u8,
+	timer: u8,
+	pub volume: u8,
+}
+
+impl Envelope {
+	pub fn new() -> Envelope {
+		Envelope {
+			initial_volume: 0,
+			increase: false,
+			period: 0,
+			timer: 0,
+			volume: 0,
+		}
+	}
+
+	// NRx2: Bits 7-4 initial volume, bit 3 direction, bits 2-0 period
+	pub fn write(&mut self, data: u8) {
+		self.initial_volume = data >> 4;
+		self.increase = (data & 0x08) > 0;
+		self.period = data & 0x07;
+	}
+
+	pub fn trigger(&mut self) {
+		self.timer = self.period;
+		self.volume = self.initial_volume;
+	}
+
+	pub fn clock(&mut self) {
+		if self.period == 0 {
+			return;
+		}
+		if self.timer > 0 {
+			self.timer -= 1;
+		}
+		if self.timer == 0 {
+			self.timer = self.period;
+			if self.increase && self.volume < 15 {
+				self.volume += 1;
+			} else if !self.increase && self.volume > 0 {
+				self.volume -= 1;
+			}
+		}
+	}
+}
+
+impl Stateful for LengthCounter {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_u16(self.counter);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.counter = state.read_u16()?;
+		Ok(())
+	}
+}
+
+impl Stateful for Envelope {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.initial_volume);
+		state.write_bool(self.increase);
+		state.write_u8(self.period);
+		state.write_u8(self.timer);
+		state.write_u8(self.volume);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.initial_volume = state.read_u8()?;
+		self.increase = state.read_bool()?;
+		self.period = state.read_u8()?;
+		self.timer = state.read_u8()?;
+		self.volume = state.read_u8()?;
+		Ok(())
+	}
+}
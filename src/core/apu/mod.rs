@@ -0,0 +1,250 @@
+use core::memory_map::*;
+use core::sink::*;
+use core::state::*;
+
+pub mod channel;
+pub mod square;
+pub mod wave;
+pub mod noise;
+
+use self::square::*;
+use self::wave::*;
+use self::noise::*;
+
+// Default output rate of the sample stream
+pub const SAMPLE_RATE: u32 = 44100;
+
+const CLOCK_SPEED: u32 = 4194304;
+
+// The frame sequencer runs at 512Hz
+const FRAME_SEQUENCER_PERIOD: u32 = CLOCK_SPEED / 512;
+
+// Bits that always read back as 1 for NR10 - NR52
+const READ_MASKS: [u8; 23] = [
+	0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10 - NR14
+	0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20 - NR24
+	0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30 - NR34
+	0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40 - NR44
+	0x00, 0x00, 0x70,             // NR50 - NR52
+];
+
+// Audio Processing Unit
+pub struct Apu {
+	square1: Square,
+	square2: Square,
+	wave: Wave,
+	noise: Noise,
+	// Raw register values from NR10 to NR51, used for reading back
+	registers: Vec<u8>,
+	enabled: bool,
+	frame_sequencer_step: u8,
+	frame_sequencer_counter: u32,
+	sample_rate: u32,
+	sample_counter: u32,
+}
+
+impl Apu {
+	pub fn new() -> Apu {
+		Apu {
+			square1: Square::new(true),
+			square2: Square::new(false),
+			wave: Wave::new(),
+			noise: Noise::new(),
+			registers: vec![0; READ_MASKS.len()],
+			enabled: false,
+			frame_sequencer_step: 0,
+			frame_sequencer_counter: 0,
+			sample_rate: SAMPLE_RATE,
+			sample_counter: 0,
+		}
+	}
+
+	// Samples per second, must not be 0
+	pub fn set_sample_rate(&mut self, sample_rate: u32) {
+		assert!(sample_rate > 0, "Sample rate must not be 0");
+		self.sample_rate = sample_rate;
+		self.sample_counter = 0;
+	}
+
+	pub fn sample_rate(&self) -> u32 {
+		self.sample_rate
+	}
+
+	pub fn read(&self, address: u16) -> u8 {
+		match address {
+			NR52 => {
+				let mut status = READ_MASKS[(NR52 - NR10) as usize];
+				if self.enabled { status |= 0x80; }
+				if self.square1.enabled { status |= 0x01; }
+				if self.square2.enabled { status |= 0x02; }
+				if self.wave.enabled { status |= 0x04; }
+				if self.noise.enabled { status |= 0x08; }
+				status
+			},
+			NR10 ... NR51 => {
+				let index = (address - NR10) as usize;
+				self.registers[index] | READ_MASKS[index]
+			},
+			WAVE_RAM_START ... WAVE_RAM_END => self.wave.read_ram(address - WAVE_RAM_START),
+			_ => 0xFF,
+		}
+	}
+
+	pub fn write(&mut self, address: u16, data: u8) {
+		match address {
+			NR52 => self.set_power((data & 0x80) > 0),
+			NR10 ... NR51 => {
+				// While powered off only the length counters can be written
+				let data = if self.enabled { data } else {
+					match address {
+						NR11 | NR21 => data & 0x3F,
+						NR31 | NR41 => data,
+						_ => return,
+					}
+				};
+				self.registers[(address - NR10) as usize] = data;
+				match address {
+					NR10 ... NR14 => self.square1.write(address - NR10, data),
+					NR21 ... NR24 => self.square2.write(address - (NR21 - 1), data),
+					NR30 ... NR34 => self.wave.write(address - NR30, data),
+					NR41 ... NR44 => self.noise.write(address - NR41, data),
+					_ => {},
+				}
+			},
+			WAVE_RAM_START ... WAVE_RAM_END => self.wave.write_ram(address - WAVE_RAM_START, data),
+			_ => {},
+		}
+	}
+
+	// Powering off the APU clears every sound register, which also turns off
+	// every DAC and so every channel. Wave RAM is left alone.
+	fn set_power(&mut self, enabled: bool) {
+		if self.enabled && !enabled {
+			for address in NR10..NR52 {
+				self.write(address, 0x00);
+			}
+		}
+		if !self.enabled && enabled {
+			self.frame_sequencer_step = 0;
+			self.frame_sequencer_counter = 0;
+		}
+		self.enabled = enabled;
+	}
+
+	// Advance the APU by a number of cycles, pushing samples into the sink at the sample rate
+	pub fn cycles(&mut self, cycles: usize, audio_sink: &mut AudioSink) {
+		let mut remaining = cycles as u32;
+		while remaining > 0 {
+			// Run up until the next event, either a sample or a frame sequencer tick
+			let until_sample = (CLOCK_SPEED - self.sample_counter + self.sample_rate - 1) / self.sample_rate;
+			let until_sequencer = FRAME_SEQUENCER_PERIOD - self.frame_sequencer_counter;
+			let step = remaining.min(until_sample.max(1)).min(until_sequencer);
+
+			if self.enabled {
+				self.square1.step(step);
+				self.square2.step(step);
+				self.wave.step(step);
+				self.noise.step(step);
+
+				self.frame_sequencer_counter += step;
+				if self.frame_sequencer_counter >= FRAME_SEQUENCER_PERIOD {
+					self.frame_sequencer_counter -= FRAME_SEQUENCER_PERIOD;
+					self.clock_frame_sequencer();
+				}
+			}
+
+			self.sample_counter += step * self.sample_rate;
+			if self.sample_counter >= CLOCK_SPEED {
+				self.sample_counter -= CLOCK_SPEED;
+				let (left, right) = self.mix();
+				audio_sink.append(left, right);
+			}
+
+			remaining -= step;
+		}
+	}
+
+	// Step   Length Ctr  Vol Env     Sweep
+	// 0      Clock       -           -
+	// 2      Clock       -           Clock
+	// 4      Clock       -           -
+	// 6      Clock       -           Clock
+	// 7      -           Clock       -
+	fn clock_frame_sequencer(&mut self) {
+		let step = self.frame_sequencer_step;
+		if step % 2 == 0 {
+			self.square1.clock_length();
+			self.square2.clock_length();
+			self.wave.clock_length();
+			self.noise.clock_length();
+		}
+		if step == 2 || step == 6 {
+			self.square1.clock_sweep();
+		}
+		if step == 7 {
+			self.square1.clock_envelope();
+			self.square2.clock_envelope();
+			self.noise.clock_envelope();
+		}
+		self.frame_sequencer_step = (step + 1) & 0x07;
+	}
+
+	// Mix all channels into a stereo sample using NR50 master volume and NR51 panning
+	fn mix(&self) -> (i16, i16) {
+		if !self.enabled {
+			return (0, 0);
+		}
+		let panning = self.registers[(NR51 - NR10) as usize];
+		let volume = self.registers[(NR50 - NR10) as usize];
+
+		// Each DAC converts 0-15 to an analog value centered on zero
+		let outputs = [
+			(self.square1.dac_enabled(), self.square1.output()),
+			(self.square2.dac_enabled(), self.square2.output()),
+			(self.wave.dac_enabled(), self.wave.output()),
+			(self.noise.dac_enabled(), self.noise.output()),
+		];
+
+		let mut left: i32 = 0;
+		let mut right: i32 = 0;
+		for (channel, &(dac_enabled, output)) in outputs.iter().enumerate() {
+			if !dac_enabled {
+				continue;
+			}
+			let analog = (output as i32 * 2) - 15;
+			if panning & (0x10 << channel) > 0 { left += analog; }
+			if panning & (0x01 << channel) > 0 { right += analog; }
+		}
+
+		let left_volume = (((volume >> 4) & 0x07) + 1) as i32;
+		let right_volume = ((volume & 0x07) + 1) as i32;
+
+		// 4 channels * 15 * 8 = 480 at most, scale to fill 16 bits
+		((left * left_volume * 64) as i16, (right * right_volume * 64) as i16)
+	}
+}
+
+impl Stateful for Apu {
+	fn save_state(&self, state: &mut StateWriter) {
+		self.square1.save_state(state);
+		self.square2.save_state(state);
+		self.wave.save_state(state);
+		self.noise.save_state(state);
+		state.write_bytes(&self.registers);
+		state.write_bool(self.enabled);
+		state.write_u8(self.frame_sequencer_step);
+		state.write_u32(self.frame_sequencer_counter);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.square1.load_state(state)?;
+		self.square2.load_state(state)?;
+		self.wave.load_state(state)?;
+		self.noise.load_state(state)?;
+		state.read_into(&mut self.registers)?;
+		self.enabled = state.read_bool()?;
+		self.frame_sequencer_step = state.read_u8()? & 0x07;
+		self.frame_sequencer_counter = state.read_u32()? % FRAME_SEQUENCER_PERIOD;
+		Ok(())
+	}
+}
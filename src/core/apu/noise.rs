@@ -0,0 +1,138 @@
+use core::apu::channel::*;
+use core::state::*;
+
+const DIVISORS: [i32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Noise channel, output comes from a linear feedback shift register
+pub struct Noise {
+	pub enabled: bool,
+	dac_enabled: bool,
+	clock_shift: u8,
+	width_mode: bool, // 7-bit LFSR when set, 15-bit otherwise
+	divisor_code: u8,
+	timer: i32,
+	lfsr: u16,
+	pub length: LengthCounter,
+	envelope: Envelope,
+}
+
+impl Noise {
+	pub fn new() -> Noise {
+		Noise {
+			enabled: false,
+			dac_enabled: false,
+			clock_shift: 0,
+			width_mode: false,
+			divisor_code: 0,
+			timer: 0,
+			lfsr: 0x7FFF,
+			length: LengthCounter::new(64),
+			envelope: Envelope::new(),
+		}
+	}
+
+	// Writes to NR41 - NR44, register is the offset from NR41
+	pub fn write(&mut self, register: u16, data: u8) {
+		match register {
+			0 => {
+				self.length.load((data & 0x3F) as u16);
+			},
+			1 => {
+				self.envelope.write(data);
+				self.dac_enabled = (data & 0xF8) > 0;
+				if !self.dac_enabled {
+					self.enabled = false;
+				}
+			},
+			2 => {
+				self.clock_shift = data >> 4;
+				self.width_mode = (data & 0x08) > 0;
+				self.divisor_code = data & 0x07;
+			},
+			3 => {
+				self.length.enabled = (data & 0x40) > 0;
+				if (data & 0x80) > 0 {
+					self.trigger();
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	fn period(&self) -> i32 {
+		DIVISORS[self.divisor_code as usize] << self.clock_shift
+	}
+
+	fn trigger(&mut self) {
+		self.enabled = self.dac_enabled;
+		self.length.trigger();
+		self.timer = self.period();
+		self.envelope.trigger();
+		self.lfsr = 0x7FFF;
+	}
+
+	pub fn step(&mut self, cycles: u32) {
+		// Shift clocks 14 and 15 receive no clocks
+		if self.clock_shift >= 14 {
+			return;
+		}
+		self.timer -= cycles as i32;
+		while self.timer <= 0 {
+			self.timer += self.period();
+			let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+			self.lfsr = (self.lfsr >> 1) | (bit << 14);
+			if self.width_mode {
+				self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+			}
+		}
+	}
+
+	pub fn clock_length(&mut self) {
+		if !self.length.clock() {
+			self.enabled = false;
+		}
+	}
+
+	pub fn clock_envelope(&mut self) {
+		self.envelope.clock();
+	}
+
+	// The output is the inverted low bit of the LFSR
+	pub fn output(&self) -> u8 {
+		if self.enabled && (self.lfsr & 1) == 0 {
+			self.envelope.volume
+		} else {
+			0
+		}
+	}
+
+	pub fn dac_enabled(&self) -> bool {
+		self.dac_enabled
+	}
+}
+
+impl Stateful for Noise {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+		state.write_u8(self.clock_shift);
+		state.write_bool(self.width_mode);
+		state.write_u8(self.divisor_code);
+		state.write_i32(self.timer);
+		state.write_u16(self.lfsr);
+		self.length.save_state(state);
+		self.envelope.save_state(state);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+		self.clock_shift = state.read_u8()? & 0x0F;
+		self.width_mode = state.read_bool()?;
+		self.divisor_code = state.read_u8()? & 0x07;
+		self.timer = state.read_i32()?;
+		self.lfsr = state.read_u16()?;
+		self.length.load_state(state)?;
+		self.envelope.load_state(state)
+	}
+}
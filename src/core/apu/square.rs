@@ -0,0 +1,219 @@
+use core::apu::channel::*;
+use core::state::*;
+
+// Output waveforms for each duty setting, 12.5%, 25%, 50% and 75%
+const DUTY_TABLE: [[u8; 8]; 4] = [
+	[0, 0, 0, 0, 0, 0, 0, 1],
+	[1, 0, 0, 0, 0, 0, 0, 1],
+	[1, 0, 0, 0, 0, 1, 1, 1],
+	[0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+// Square wave channel. Channel 1 has a frequency sweep unit, channel 2 does not
+pub struct Square {
+	pub enabled: bool,
+	dac_enabled: bool,
+	duty: u8,
+	duty_step: u8,
+	frequency: u16,
+	timer: i32,
+	pub length: LengthCounter,
+	envelope: Envelope,
+	// Sweep unit
+	has_sweep: bool,
+	sweep_enabled: bool,
+	sweep_period: u8,
+	sweep_negate: bool,
+	sweep_shift: u8,
+	sweep_timer: u8,
+	sweep_shadow: u16,
+	sweep_negated: bool, // a negate calculation happened since the last trigger
+}
+
+impl Square {
+	pub fn new(has_sweep: bool) -> Square {
+		Square {
+			enabled: false,
+			dac_enabled: false,
+			duty: 0,
+			duty_step: 0,
+			frequency: 0,
+			timer: 0,
+			length: LengthCounter::new(64),
+			envelope: Envelope::new(),
+			has_sweep: has_sweep,
+			sweep_enabled: false,
+			sweep_period: 0,
+			sweep_negate: false,
+			sweep_shift: 0,
+			sweep_timer: 0,
+			sweep_shadow: 0,
+			sweep_negated: false,
+		}
+	}
+
+	// Writes to NRx0 - NRx4, register is the offset from NRx0
+	pub fn write(&mut self, register: u16, data: u8) {
+		match register {
+			0 => {
+				self.sweep_period = (data >> 4) & 0x07;
+				self.sweep_negate = (data & 0x08) > 0;
+				self.sweep_shift = data & 0x07;
+				// Leaving negate mode after it was used disables the channel
+				if !self.sweep_negate && self.sweep_negated {
+					self.enabled = false;
+				}
+			},
+			1 => {
+				self.duty = data >> 6;
+				self.length.load((data & 0x3F) as u16);
+			},
+			2 => {
+				self.envelope.write(data);
+				self.dac_enabled = (data & 0xF8) > 0;
+				if !self.dac_enabled {
+					self.enabled = false;
+				}
+			},
+			3 => {
+				self.frequency = (self.frequency & 0x700) | data as u16;
+			},
+			4 => {
+				self.frequency = (self.frequency & 0xFF) | (((data & 0x07) as u16) << 8);
+				self.length.enabled = (data & 0x40) > 0;
+				if (data & 0x80) > 0 {
+					self.trigger();
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	fn period(&self) -> i32 {
+		(2048 - self.frequency as i32) * 4
+	}
+
+	fn trigger(&mut self) {
+		self.enabled = self.dac_enabled;
+		self.length.trigger();
+		self.timer = self.period();
+		self.envelope.trigger();
+
+		if self.has_sweep {
+			self.sweep_shadow = self.frequency;
+			self.sweep_timer = if self.sweep_period == 0 { 8 } else { self.sweep_period };
+			self.sweep_enabled = self.sweep_period != 0 || self.sweep_shift != 0;
+			self.sweep_negated = false;
+			if self.sweep_shift != 0 {
+				self.sweep_calculate();
+			}
+		}
+	}
+
+	// Advance the frequency timer by a number of cycles
+	pub fn step(&mut self, cycles: u32) {
+		self.timer -= cycles as i32;
+		while self.timer <= 0 {
+			self.timer += self.period();
+			self.duty_step = (self.duty_step + 1) & 0x07;
+		}
+	}
+
+	pub fn clock_length(&mut self) {
+		if !self.length.clock() {
+			self.enabled = false;
+		}
+	}
+
+	pub fn clock_envelope(&mut self) {
+		self.envelope.clock();
+	}
+
+	pub fn clock_sweep(&mut self) {
+		if !self.has_sweep {
+			return;
+		}
+		if self.sweep_timer > 0 {
+			self.sweep_timer -= 1;
+		}
+		if self.sweep_timer == 0 {
+			self.sweep_timer = if self.sweep_period == 0 { 8 } else { self.sweep_period };
+			if self.sweep_enabled && self.sweep_period != 0 {
+				let frequency = self.sweep_calculate();
+				if frequency <= 2047 && self.sweep_shift != 0 {
+					self.sweep_shadow = frequency;
+					self.frequency = frequency;
+					// The new frequency is checked for overflow again, but not used
+					self.sweep_calculate();
+				}
+			}
+		}
+	}
+
+	// Calculates the next sweep frequency, disabling the channel if it overflows
+	fn sweep_calculate(&mut self) -> u16 {
+		let delta = self.sweep_shadow >> self.sweep_shift;
+		let frequency = if self.sweep_negate {
+			self.sweep_negated = true;
+			self.sweep_shadow.wrapping_sub(delta)
+		} else {
+			self.sweep_shadow + delta
+		};
+		if frequency > 2047 {
+			self.enabled = false;
+		}
+		frequency
+	}
+
+	// Current digital output, 0-15
+	pub fn output(&self) -> u8 {
+		if self.enabled && DUTY_TABLE[self.duty as usize][self.duty_step as usize] == 1 {
+			self.envelope.volume
+		} else {
+			0
+		}
+	}
+
+	pub fn dac_enabled(&self) -> bool {
+		self.dac_enabled
+	}
+}
+
+impl Stateful for Square {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+		state.write_u8(self.duty);
+		state.write_u8(self.duty_step);
+		state.write_u16(self.frequency);
+		state.write_i32(self.timer);
+		self.length.save_state(state);
+		self.envelope.save_state(state);
+		state.write_bool(self.sweep_enabled);
+		state.write_u8(self.sweep_period);
+		state.write_bool(self.sweep_negate);
+		state.write_u8(self.sweep_shift);
+		state.write_u8(self.sweep_timer);
+		state.write_u16(self.sweep_shadow);
+		state.write_bool(self.sweep_negated);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+		self.duty = state.read_u8()? & 0x03;
+		self.duty_step = state.read_u8()? & 0x07;
+		self.frequency = state.read_u16()? & 0x7FF;
+		self.timer = state.read_i32()?;
+		self.length.load_state(state)?;
+		self.envelope.load_state(state)?;
+		self.sweep_enabled = state.read_bool()?;
+		self.sweep_period = state.read_u8()? & 0x07;
+		self.sweep_negate = state.read_bool()?;
+		self.sweep_shift = state.read_u8()? & 0x07;
+		self.sweep_timer = state.read_u8()?;
+		self.sweep_shadow = state.read_u16()?;
+		self.sweep_negated = state.read_bool()?;
+		Ok(())
+	}
+}
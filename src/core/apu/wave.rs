@@ -0,0 +1,145 @@
+use core::apu::channel::*;
+use core::state::*;
+
+const WAVE_RAM_SIZE: usize = 16; // 32 4-bit samples
+
+// Wave channel, plays back 32 user-defined samples from wave RAM
+pub struct Wave {
+	pub enabled: bool,
+	dac_enabled: bool,
+	frequency: u16,
+	timer: i32,
+	position: u8,
+	volume_shift: u8,
+	pub length: LengthCounter,
+	ram: Vec<u8>,
+}
+
+impl Wave {
+	pub fn new() -> Wave {
+		Wave {
+			enabled: false,
+			dac_enabled: false,
+			frequency: 0,
+			timer: 0,
+			position: 0,
+			volume_shift: 4,
+			length: LengthCounter::new(256),
+			ram: vec![0; WAVE_RAM_SIZE],
+		}
+	}
+
+	// Writes to NR30 - NR34, register is the offset from NR30
+	pub fn write(&mut self, register: u16, data: u8) {
+		match register {
+			0 => {
+				self.dac_enabled = (data & 0x80) > 0;
+				if !self.dac_enabled {
+					self.enabled = false;
+				}
+			},
+			1 => {
+				self.length.load(data as u16);
+			},
+			2 => {
+				// 0: mute, 1: 100%, 2: 50%, 3: 25%
+				self.volume_shift = match (data >> 5) & 0x03 {
+					0 => 4,
+					1 => 0,
+					2 => 1,
+					3 => 2,
+					_ => unreachable!(),
+				};
+			},
+			3 => {
+				self.frequency = (self.frequency & 0x700) | data as u16;
+			},
+			4 => {
+				self.frequency = (self.frequency & 0xFF) | (((data & 0x07) as u16) << 8);
+				self.length.enabled = (data & 0x40) > 0;
+				if (data & 0x80) > 0 {
+					self.trigger();
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	fn period(&self) -> i32 {
+		(2048 - self.frequency as i32) * 2
+	}
+
+	fn trigger(&mut self) {
+		self.enabled = self.dac_enabled;
+		self.length.trigger();
+		self.timer = self.period();
+		self.position = 0;
+	}
+
+	pub fn step(&mut self, cycles: u32) {
+		self.timer -= cycles as i32;
+		while self.timer <= 0 {
+			self.timer += self.period();
+			self.position = (self.position + 1) & 0x1F;
+		}
+	}
+
+	pub fn clock_length(&mut self) {
+		if !self.length.clock() {
+			self.enabled = false;
+		}
+	}
+
+	pub fn output(&self) -> u8 {
+		if !self.enabled {
+			return 0;
+		}
+		let byte = self.ram[(self.position / 2) as usize];
+		// The high nibble is played first
+		let sample = match self.position % 2 {
+			0 => byte >> 4,
+			_ => byte & 0x0F,
+		};
+		sample >> self.volume_shift
+	}
+
+	pub fn dac_enabled(&self) -> bool {
+		self.dac_enabled
+	}
+
+	pub fn read_ram(&self, index: u16) -> u8 {
+		self.ram[index as usize]
+	}
+
+	pub fn write_ram(&mut self, index: u16, data: u8) {
+		self.ram[index as usize] = data;
+	}
+}
+
+impl Stateful for Wave {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bool(self.enabled);
+		state.write_bool(self.dac_enabled);
+		state.write_u16(self.frequency);
+		state.write_i32(self.timer);
+		state.write_u8(self.position);
+		state.write_u8(self.volume_shift);
+		self.length.save_state(state);
+		state.write_bytes(&self.ram);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enabled = state.read_bool()?;
+		self.dac_enabled = state.read_bool()?;
+		self.frequency = state.read_u16()? & 0x7FF;
+		self.timer = state.read_i32()?;
+		self.position = state.read_u8()? & 0x1F;
+		// Only the shifts NR32 can select
+		self.volume_shift = match state.read_u8()? {
+			shift @ 0 ... 2 | shift @ 4 => shift,
+			_ => return Err(StateError::InvalidValue),
+		};
+		self.length.load_state(state)?;
+		state.read_into(&mut self.ram)
+	}
+}
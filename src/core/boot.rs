@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::Read;
+use core::rom::*;
+use core::register::*;
+use core::memory_map::*;
+use core::error::*;
+
+// DMG/MGB boot ROMs are 256 bytes, the CGB boot ROM is 2KB plus the 256 byte hole for the cartridge header
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+// Which hardware is being emulated, decides the state the boot ROM leaves behind
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Model {
+	Dmg,
+	Mgb,
+	Cgb,
+}
+
+impl Model {
+	// Color games run on a CGB, everything else on the original Game Boy
+	pub fn for_rom(rom: &Rom) -> Model {
+		match rom.cgb_flag() & 0x80 {
+			0 => Model::Dmg,
+			_ => Model::Cgb,
+		}
+	}
+
+	// CPU registers as the boot ROM leaves them
+	pub fn post_boot_registers(&self, cgb_game: bool) -> Registers {
+		let mut regs = Registers::new();
+		regs.sp = 0xFFFE;
+		regs.pc = 0x0100;
+		match *self {
+			Model::Dmg | Model::Mgb => {
+				regs.a = match *self { Model::Dmg => 0x01, _ => 0xFF };
+				regs.f = 0xB0;
+				regs.b = 0x00; regs.c = 0x13;
+				regs.d = 0x00; regs.e = 0xD8;
+				regs.h = 0x01; regs.l = 0x4D;
+			},
+			Model::Cgb => {
+				// A = 0x11 is how games detect they are running on color hardware
+				regs.a = 0x11;
+				regs.f = 0x80;
+				regs.b = 0x00; regs.c = 0x00;
+				match cgb_game {
+					true => { regs.d = 0xFF; regs.e = 0x56; regs.h = 0x00; regs.l = 0x0D; },
+					false => { regs.d = 0x00; regs.e = 0x08; regs.h = 0x00; regs.l = 0x7C; },
+				}
+			},
+		}
+		regs
+	}
+
+	// Upper byte of the internal divider after the boot ROM has run
+	pub fn post_boot_div(&self) -> u8 {
+		match *self {
+			Model::Dmg | Model::Mgb => 0xAB,
+			Model::Cgb => 0x00,
+		}
+	}
+}
+
+// IO registers as the boot ROM leaves them, written in this order.
+// NR52 comes first since the APU ignores writes while it is powered off.
+pub const POST_BOOT_IO: [(u16, u8); 28] = [
+	(NR52, 0xF1),
+	(NR10, 0x80), (NR11, 0xBF), (NR12, 0xF3), (NR13, 0xFF), (NR14, 0xBF),
+	(NR21, 0x3F), (NR22, 0x00), (NR23, 0xFF), (NR24, 0xBF),
+	(NR30, 0x7F), (NR31, 0xFF), (NR32, 0x9F), (NR33, 0xFF), (NR34, 0xBF),
+	(NR41, 0xFF), (NR42, 0x00), (NR43, 0x00), (NR44, 0xBF),
+	(NR50, 0x77), (NR51, 0xF3),
+	(TAC, 0xF8),
+	(LCDC, 0x91),
+	(BGP, 0xFC),
+	(OBP0, 0xFF),
+	(OBP1, 0xFF),
+	(IF, 0xE1),
+	(IE, 0x00),
+];
+
+pub struct BootRom {
+	bytes: Vec<u8>,
+	model: Model,
+}
+
+impl BootRom {
+	// Load a boot ROM image, the model is guessed from its size.
+	// MGB boot ROMs are the same size as DMG ones and have to be given with from_bytes.
+	pub fn load(path: String) -> Result<BootRom, EmuError> {
+		let mut buffer = Vec::new();
+		let mut file = File::open(path)?;
+		file.read_to_end(&mut buffer)?;
+		let model = match buffer.len() {
+			CGB_BOOT_ROM_SIZE => Model::Cgb,
+			_ => Model::Dmg,
+		};
+		BootRom::from_bytes(buffer, model)
+	}
+
+	pub fn from_bytes(bytes: Vec<u8>, model: Model) -> Result<BootRom, EmuError> {
+		let expected = match model {
+			Model::Dmg | Model::Mgb => DMG_BOOT_ROM_SIZE,
+			Model::Cgb => CGB_BOOT_ROM_SIZE,
+		};
+		if bytes.len() != expected {
+			return Err(EmuError::InvalidBootRom(bytes.len()));
+		}
+		Ok(BootRom {
+			bytes: bytes,
+			model: model,
+		})
+	}
+
+	pub fn model(&self) -> Model {
+		self.model
+	}
+
+	pub fn bytes(&self) -> &Vec<u8> {
+		&self.bytes
+	}
+
+	// The cartridge header at 0x0100-0x01FF always shows through
+	pub fn is_mapped(&self, address: u16) -> bool {
+		let address = address as usize;
+		address < DMG_BOOT_ROM_SIZE || (address >= 0x200 && address < self.bytes.len())
+	}
+
+	pub fn read(&self, address: u16) -> u8 {
+		self.bytes[address as usize]
+	}
+}
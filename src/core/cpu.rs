@@ -2,6 +2,8 @@ use core::register::*;
 use core::interconnect::*;
 use core::helper::*;
 use core::disassembler::*;
+use core::state::*;
+use core::error::*;
 
 enum Condition {
 	NotZero  = 0b00,
@@ -25,6 +27,12 @@ enum RstVector {
 pub struct CPU {
 	pub regs: Registers,
 	halted: bool,
+	// HALT with IME off and an interrupt pending doesn't halt, the next opcode byte is read twice
+	halt_bug: bool,
+	// Low power mode after STOP, only a button press wakes the CPU
+	stopped: bool,
+	// Opcode and address of the illegal instruction that locked up the CPU
+	lock: Option<(u8, u16)>,
 }
 
 impl CPU {
@@ -34,41 +42,78 @@ impl CPU {
 		CPU {
 			regs: Registers::new(),
 			halted: false,
+			halt_bug: false,
+			stopped: false,
+			lock: None,
 		}
 	}
 
 	// Reads the next byte and increments the program counter
-	fn next_byte(&mut self, memory: &Interconnect) -> u8 {
-		let byte = memory.read(self.regs.pc);
+	fn next_byte(&mut self, memory: &mut Interconnect) -> u8 {
+		let byte = memory.read_cycle(self.regs.pc);
 		self.regs.pc = self.regs.pc.wrapping_add(1);
 		byte
 	}
 
 	// Returns the next word (pointer, little endian)
-	fn next_pointer(&mut self, memory: &Interconnect) -> u16 {
+	fn next_pointer(&mut self, memory: &mut Interconnect) -> u16 {
 		let low  = self.next_byte(memory);
 		let high = self.next_byte(memory);
 		combine!(high, low)
 	}
 
 	// Perform one step of the fetch-decode-execute cycle
-	pub fn step(&mut self, memory: &mut Interconnect) -> usize {
+	pub fn step(&mut self, memory: &mut Interconnect) -> Result<usize, EmuError> {
 
-		self.handle_interrupts(memory);
+		// Not even interrupts get a locked up CPU going again. Like a HALT that never ends,
+		// the rest of the machine keeps running.
+		if self.lock.is_some() {
+			return Ok(memory.finish_instruction(1));
+		}
+
+		if self.stopped {
+			// Any selected button line going low ends STOP
+			if memory.joypad.read() & 0x0F == 0x0F {
+				return Ok(1);
+			}
+			self.stopped = false;
+		}
 
+		match self.execute(memory) {
+			// Cycles without a bus access, like the delay before a jump, are run after the last access
+			Ok(cycles) => Ok(memory.finish_instruction(cycles)),
+			Err(e) => {
+				memory.finish_instruction(0);
+				Err(e)
+			}
+		}
+	}
+
+	// Runs the next instruction or interrupt dispatch and returns the machine cycles it takes.
+	// Every bus access made along the way runs the rest of the machine for one machine cycle.
+	fn execute(&mut self, memory: &mut Interconnect) -> Result<usize, EmuError> {
+
+		// HALT ends as soon as an interrupt is pending, even if IME is off and it isn't handled
 		if self.halted {
-			return 1;
+			if memory.interrupt.pending() == 0 {
+				return Ok(1);
+			}
+			self.halted = false;
+		}
+
+		if let Some(cycles) = self.handle_interrupts(memory) {
+			return Ok(cycles);
 		}
 
 		let old_pc = self.regs.pc;
 		let opcode = self.next_byte(memory);
-		//let command = disassemble(&self.regs, &memory, opcode);
-		//println!("{}", command);
-
-		//println!("PC: 0x{:04X}: ${:02X}", self.regs.pc - 1, opcode);
+		if self.halt_bug {
+			self.halt_bug = false;
+			self.regs.pc = old_pc;
+		}
 
 		// decodes/excecutes each operation and returns cycles taken
-		match opcode {
+		let cycles = match opcode {
 			// 8-bit transfers
 			// LD r, r'
 			0x7F => { 1 }, // LD A, A
@@ -129,67 +174,67 @@ impl CPU {
 			0x26 => { self.regs.h = self.next_byte(memory); 2 },
 			0x2E => { self.regs.l = self.next_byte(memory); 2 },
 			// LD r, (HL)
-			0x7E => { self.regs.a = memory.read(self.regs.hl()); 2 },
-			0x46 => { self.regs.b = memory.read(self.regs.hl()); 2 },
-			0x4E => { self.regs.c = memory.read(self.regs.hl()); 2 },
-			0x56 => { self.regs.d = memory.read(self.regs.hl()); 2 },
-			0x5E => { self.regs.e = memory.read(self.regs.hl()); 2 },
-			0x66 => { self.regs.h = memory.read(self.regs.hl()); 2 },
-			0x6E => { self.regs.l = memory.read(self.regs.hl()); 2 },
+			0x7E => { self.regs.a = memory.read_cycle(self.regs.hl()); 2 },
+			0x46 => { self.regs.b = memory.read_cycle(self.regs.hl()); 2 },
+			0x4E => { self.regs.c = memory.read_cycle(self.regs.hl()); 2 },
+			0x56 => { self.regs.d = memory.read_cycle(self.regs.hl()); 2 },
+			0x5E => { self.regs.e = memory.read_cycle(self.regs.hl()); 2 },
+			0x66 => { self.regs.h = memory.read_cycle(self.regs.hl()); 2 },
+			0x6E => { self.regs.l = memory.read_cycle(self.regs.hl()); 2 },
 			// LD (HL), r
-			0x77 => { memory.write(self.regs.hl(), self.regs.a); 2 },
-			0x70 => { memory.write(self.regs.hl(), self.regs.b); 2 },
-			0x71 => { memory.write(self.regs.hl(), self.regs.c); 2 },
-			0x72 => { memory.write(self.regs.hl(), self.regs.d); 2 },
-			0x73 => { memory.write(self.regs.hl(), self.regs.e); 2 },
-			0x74 => { memory.write(self.regs.hl(), self.regs.h); 2 },
-			0x75 => { memory.write(self.regs.hl(), self.regs.l); 2 },
+			0x77 => { memory.write_cycle(self.regs.hl(), self.regs.a); 2 },
+			0x70 => { memory.write_cycle(self.regs.hl(), self.regs.b); 2 },
+			0x71 => { memory.write_cycle(self.regs.hl(), self.regs.c); 2 },
+			0x72 => { memory.write_cycle(self.regs.hl(), self.regs.d); 2 },
+			0x73 => { memory.write_cycle(self.regs.hl(), self.regs.e); 2 },
+			0x74 => { memory.write_cycle(self.regs.hl(), self.regs.h); 2 },
+			0x75 => { memory.write_cycle(self.regs.hl(), self.regs.l); 2 },
 			// LD (HL), n
-			0x36 => { let n = self.next_byte(memory); memory.write(self.regs.hl(), n); 3 },
+			0x36 => { let n = self.next_byte(memory); memory.write_cycle(self.regs.hl(), n); 3 },
 			// LD A, (BC)
-			0x0A => { self.regs.a = memory.read(self.regs.bc()); 2 },
+			0x0A => { self.regs.a = memory.read_cycle(self.regs.bc()); 2 },
 			// LD A, (DE)
-			0x1A => { self.regs.a = memory.read(self.regs.de()); 2 },
+			0x1A => { self.regs.a = memory.read_cycle(self.regs.de()); 2 },
 			// LD A, (C)
 			0xF2 => {
 				let offset = 0xFF00 | self.regs.c as u16;
-				self.regs.a = memory.read(offset);
+				self.regs.a = memory.read_cycle(offset);
 				2
 			},
 			// LD (C), A
 			0xE2 => {
 				let offset = 0xFF00 | self.regs.c as u16;
-				memory.write(offset, self.regs.a);
+				memory.write_cycle(offset, self.regs.a);
 				2
 			},
 			// LD A, (n)
 			0xF0 => {
 				let offset = 0xFF00 | self.next_byte(memory) as u16;
-				self.regs.a = memory.read(offset);
+				self.regs.a = memory.read_cycle(offset);
 				3
 			},
 			// LD (n), A
 			0xE0 => {
 				let offset = 0xFF00 | self.next_byte(memory) as u16;
-				memory.write(offset, self.regs.a);
+				memory.write_cycle(offset, self.regs.a);
 				3
 			},
 			// LD A, (nn)
-			0xFA => { self.regs.a = memory.read(self.next_pointer(memory)); 4 },
+			0xFA => { let address = self.next_pointer(memory); self.regs.a = memory.read_cycle(address); 4 },
 			// LD (nn), A
-			0xEA => { let addr = self.next_pointer(memory); memory.write(addr, self.regs.a); 4 },
+			0xEA => { let addr = self.next_pointer(memory); memory.write_cycle(addr, self.regs.a); 4 },
 			// LD A (HLI)
-			0x2A => { self.regs.a = memory.read(self.regs.hl()); self.regs.hli(); 2 },
+			0x2A => { self.regs.a = memory.read_cycle(self.regs.hl()); self.regs.hli(); 2 },
 			// LD A (HLD)
-			0x3A => { self.regs.a = memory.read(self.regs.hl()); self.regs.hld(); 2 },
+			0x3A => { self.regs.a = memory.read_cycle(self.regs.hl()); self.regs.hld(); 2 },
 			// LD (BC), A
-			0x02 => { memory.write(self.regs.bc(), self.regs.a); 2 },
+			0x02 => { memory.write_cycle(self.regs.bc(), self.regs.a); 2 },
 			// LD (DE), A
-			0x12 => { memory.write(self.regs.de(), self.regs.a); 2 },
+			0x12 => { memory.write_cycle(self.regs.de(), self.regs.a); 2 },
 			// LD (HLI), A
-			0x22 => { memory.write(self.regs.hl(), self.regs.a); self.regs.hli(); 2 },
+			0x22 => { memory.write_cycle(self.regs.hl(), self.regs.a); self.regs.hli(); 2 },
 			// LD (HLD), A
-			0x32 => { memory.write(self.regs.hl(), self.regs.a); self.regs.hld(); 2 },
+			0x32 => { memory.write_cycle(self.regs.hl(), self.regs.a); self.regs.hld(); 2 },
 
 			// 16-bit transfers
 			// LD dd, nn
@@ -220,8 +265,8 @@ impl CPU {
 			// LD (nn), SP
 			0x08 => {
 				let nn = self.next_pointer(memory);
-				memory.write(nn, low!(self.regs.sp));
-				memory.write(nn + 1, high!(self.regs.sp));
+				memory.write_cycle(nn, low!(self.regs.sp));
+				memory.write_cycle(nn + 1, high!(self.regs.sp));
 				5
 			},
 			// 8-bit Arithmetic
@@ -236,7 +281,7 @@ impl CPU {
 			// ADD A, n
 			0xC6 => { let n = self.next_byte(memory); self.add_u8(n, false); 2 },
 			// ADD A, (HL)
-			0x86 => { let hl = memory.read(self.regs.hl()); self.add_u8(hl, false); 2 },
+			0x86 => { let hl = memory.read_cycle(self.regs.hl()); self.add_u8(hl, false); 2 },
 			// ADC A, r
 			0x8F => { let r = self.regs.a; self.add_u8(r, true); 1 },
 			0x88 => { let r = self.regs.b; self.add_u8(r, true); 1 },
@@ -248,7 +293,7 @@ impl CPU {
 			// ADC A, n
 			0xCE => { let n = self.next_byte(memory); self.add_u8(n, true); 2 },
 			// ADC A, (HL)
-			0x8E => { let hl = memory.read(self.regs.hl()); self.add_u8(hl, true); 2 },
+			0x8E => { let hl = memory.read_cycle(self.regs.hl()); self.add_u8(hl, true); 2 },
 			// SUB A, r
 			0x97 => { let r = self.regs.a; self.sub_u8(r, false); 1 },
 			0x90 => { let r = self.regs.b; self.sub_u8(r, false); 1 },
@@ -260,7 +305,7 @@ impl CPU {
 			// SUB A, n
 			0xD6 => { let n = self.next_byte(memory); self.sub_u8(n, false); 2 },
 			// SUB A, (HL)
-			0x96 => { let hl = memory.read(self.regs.hl()); self.sub_u8(hl, false); 2 },
+			0x96 => { let hl = memory.read_cycle(self.regs.hl()); self.sub_u8(hl, false); 2 },
 			// SBC A, r
 			0x9F => { let r = self.regs.a; self.sub_u8(r, true); 1 },
 			0x98 => { let r = self.regs.b; self.sub_u8(r, true); 1 },
@@ -272,7 +317,7 @@ impl CPU {
 			// SBC A, n
 			0xDE => { let n = self.next_byte(memory); self.sub_u8(n, true); 2 },
 			// SBC A, (HL)
-			0x9E => { let hl = memory.read(self.regs.hl()); self.sub_u8(hl, true); 2 },
+			0x9E => { let hl = memory.read_cycle(self.regs.hl()); self.sub_u8(hl, true); 2 },
 			// AND A, r
 			0xA7 => { let r = self.regs.a; self.and_u8(r); 1 },
 			0xA0 => { let r = self.regs.b; self.and_u8(r); 1 },
@@ -284,7 +329,7 @@ impl CPU {
 			// AND A, n
 			0xE6 => { let n = self.next_byte(memory); self.and_u8(n); 2 },
 			// AND A, (HL)
-			0xA6 => { let hl = memory.read(self.regs.hl()); self.and_u8(hl); 2 },
+			0xA6 => { let hl = memory.read_cycle(self.regs.hl()); self.and_u8(hl); 2 },
 			// OR A, r
 			0xB7 => { let r = self.regs.a; self.or_u8(r); 1 },
 			0xB0 => { let r = self.regs.b; self.or_u8(r); 1 },
@@ -296,7 +341,7 @@ impl CPU {
 			// OR A, n
 			0xF6 => { let n = self.next_byte(memory); self.or_u8(n); 2 },
 			// OR A, (HL)
-			0xB6 => { let hl = memory.read(self.regs.hl()); self.or_u8(hl); 2 },
+			0xB6 => { let hl = memory.read_cycle(self.regs.hl()); self.or_u8(hl); 2 },
 			// XOR A, r
 			0xAF => { let r = self.regs.a; self.xor_u8(r); 1 },
 			0xA8 => { let r = self.regs.b; self.xor_u8(r); 1 },
@@ -308,7 +353,7 @@ impl CPU {
 			// XOR A, n
 			0xEE => { let n = self.next_byte(memory); self.xor_u8(n); 2 },
 			// XOR A, (HL)
-			0xAE => { let hl = memory.read(self.regs.hl()); self.xor_u8(hl); 2 },
+			0xAE => { let hl = memory.read_cycle(self.regs.hl()); self.xor_u8(hl); 2 },
 			// CP A, r
 			0xBF => { let r = self.regs.a; self.cp_u8(r); 1 },
 			0xB8 => { let r = self.regs.b; self.cp_u8(r); 1 },
@@ -320,7 +365,7 @@ impl CPU {
 			// CP A, n
 			0xFE => { let n = self.next_byte(memory); self.cp_u8(n); 2 },
 			// CP A, (HL)
-			0xBE => { let hl = memory.read(self.regs.hl()); self.cp_u8(hl); 2 },
+			0xBE => { let hl = memory.read_cycle(self.regs.hl()); self.cp_u8(hl); 2 },
 			// INC r
 			0x3C => { let r = self.regs.a; self.regs.a = self.inc_u8(r); 1 },
 			0x04 => { let r = self.regs.b; self.regs.b = self.inc_u8(r); 1 },
@@ -330,7 +375,7 @@ impl CPU {
 			0x24 => { let r = self.regs.h; self.regs.h = self.inc_u8(r); 1 },
 			0x2C => { let r = self.regs.l; self.regs.l = self.inc_u8(r); 1 },
 			// INC (HL)
-			0x34 => { let n = memory.read(self.regs.hl()); memory.write(self.regs.hl(), self.inc_u8(n)); 3 },
+			0x34 => { let n = memory.read_cycle(self.regs.hl()); memory.write_cycle(self.regs.hl(), self.inc_u8(n)); 3 },
 			// DEC r
 			0x3D => { let r = self.regs.a; self.regs.a = self.dec_u8(r); 1 },
 			0x05 => { let r = self.regs.b; self.regs.b = self.dec_u8(r); 1 },
@@ -340,7 +385,7 @@ impl CPU {
 			0x25 => { let r = self.regs.h; self.regs.h = self.dec_u8(r); 1 },
 			0x2D => { let r = self.regs.l; self.regs.l = self.dec_u8(r); 1 },
 			// DEC (HL)
-			0x35 => { let n = memory.read(self.regs.hl()); memory.write(self.regs.hl(), self.dec_u8(n)); 3 },
+			0x35 => { let n = memory.read_cycle(self.regs.hl()); memory.write_cycle(self.regs.hl(), self.dec_u8(n)); 3 },
 			// ADD HL, rr
 			0x09 => { let rr = self.regs.bc(); self.add_hl(rr); 2 },
 			0x19 => { let rr = self.regs.de(); self.add_hl(rr); 2 },
@@ -417,19 +462,34 @@ impl CPU {
 			// ccf
 			0x3F => { self.ccf(); 1 },
 			// HALT
-			0x76 => { self.halted = true; 1 },
+			0x76 => { self.halt(memory); 1 },
 			// STOP
-			0x10 => { 1 },
+			0x10 => { self.stop(memory); 1 },
 			// NOP
 			0x00 => { 1 }, // easiest opcode of my life
 
 			// GBCPUMAN
 			0xF3 => { memory.interrupt.disable(); 1 }, // Disable interrupts
-			0xFB => { memory.interrupt.enable();  1 }, // Enable interrupts
+			0xFB => { memory.interrupt.enable_delayed(); 1 }, // Enable interrupts after the next instruction
 
 			0xC3 => { self.regs.pc = self.next_pointer(memory); 4 },
-			_ => panic!("Unknown Opcode: ${:02X} @ ${:04X} dec: {}", opcode, old_pc, opcode)
-		}
+			// 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC and 0xFD don't exist
+			_ => {
+				self.lock = Some((opcode, old_pc));
+				return Err(EmuError::CpuLocked { opcode: opcode, address: old_pc });
+			}
+		};
+		memory.interrupt.update_enable_delay();
+		Ok(cycles)
+	}
+
+	// Nothing runs until a button is pressed, not even the rest of the machine
+	pub fn stopped(&self) -> bool {
+		self.stopped
+	}
+
+	pub fn locked(&self) -> bool {
+		self.lock.is_some()
 	}
 
 	// Evaluates all sub-operations under CPU instr 0xCB
@@ -448,8 +508,8 @@ impl CPU {
 			0x04 => { self.regs.h = self.rotate_left(regs.h, false, true); 2 },
 			0x05 => { self.regs.l = self.rotate_left(regs.l, false, true); 2 },
 			0x06 => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.rotate_left(n, false, true));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.rotate_left(n, false, true));
 				4
 			},
 			0x17 => { self.regs.a = self.rotate_left(regs.a, true, true); 2 },
@@ -460,8 +520,8 @@ impl CPU {
 			0x14 => { self.regs.h = self.rotate_left(regs.h, true, true); 2 },
 			0x15 => { self.regs.l = self.rotate_left(regs.l, true, true); 2 },
 			0x16 => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.rotate_left(n, true, true));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.rotate_left(n, true, true));
 				4
 			},
 			// Rotate Right
@@ -473,8 +533,8 @@ impl CPU {
 			0x0C => { self.regs.h = self.rotate_right(regs.h, false, true); 2 },
 			0x0D => { self.regs.l = self.rotate_right(regs.l, false, true); 2 },
 			0x0E => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.rotate_right(n, false, true));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.rotate_right(n, false, true));
 				4
 			},
 			0x1F => { self.regs.a = self.rotate_right(regs.a, true, true); 2 },
@@ -485,8 +545,8 @@ impl CPU {
 			0x1C => { self.regs.h = self.rotate_right(regs.h, true, true); 2 },
 			0x1D => { self.regs.l = self.rotate_right(regs.l, true, true); 2 },
 			0x1E => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.rotate_right(n, true, true));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.rotate_right(n, true, true));
 				4
 			},
 			// Shift Left
@@ -498,8 +558,8 @@ impl CPU {
 			0x24 => { self.regs.h = self.shift_left(regs.h); 2 },
 			0x25 => { self.regs.l = self.shift_left(regs.l); 2 },
 			0x26 => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.shift_left(n));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.shift_left(n));
 				4
 			},
 			// Shift Right
@@ -511,8 +571,8 @@ impl CPU {
 			0x2C => { self.regs.h = self.shift_right(regs.h, true); 2 },
 			0x2D => { self.regs.l = self.shift_right(regs.l, true); 2 },
 			0x2E => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.shift_right(n, true));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.shift_right(n, true));
 				4
 			},
 			0x3F => { self.regs.a = self.shift_right(regs.a, false); 2 },
@@ -523,8 +583,8 @@ impl CPU {
 			0x3C => { self.regs.h = self.shift_right(regs.h, false); 2 },
 			0x3D => { self.regs.l = self.shift_right(regs.l, false); 2 },
 			0x3E => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.shift_right(n, false));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.shift_right(n, false));
 				4
 			},
 			// Swap
@@ -536,8 +596,8 @@ impl CPU {
 			0x34 => { self.regs.h = self.swap(regs.h); 2 },
 			0x35 => { self.regs.l = self.swap(regs.l); 2 },
 			0x36 => {
-				let n = memory.read(regs.hl());
-				memory.write(regs.hl(), self.swap(n));
+				let n = memory.read_cycle(regs.hl());
+				memory.write_cycle(regs.hl(), self.swap(n));
 				4
 			},
 			// Bit
@@ -597,14 +657,14 @@ impl CPU {
 			0x6D => { self.bit(regs.l, Bit::Bit5); 2 },
 			0x75 => { self.bit(regs.l, Bit::Bit6); 2 },
 			0x7D => { self.bit(regs.l, Bit::Bit7); 2 },
-			0x46 => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit0); 3 },
-			0x4E => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit1); 3 },
-			0x56 => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit2); 3 },
-			0x5E => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit3); 3 },
-			0x66 => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit4); 3 },
-			0x6E => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit5); 3 },
-			0x76 => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit6); 3 },
-			0x7E => { let n = memory.read(regs.hl()); self.bit(n, Bit::Bit7); 3 },
+			0x46 => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit0); 3 },
+			0x4E => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit1); 3 },
+			0x56 => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit2); 3 },
+			0x5E => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit3); 3 },
+			0x66 => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit4); 3 },
+			0x6E => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit5); 3 },
+			0x76 => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit6); 3 },
+			0x7E => { let n = memory.read_cycle(regs.hl()); self.bit(n, Bit::Bit7); 3 },
 			// Set
 			0xC7 => { self.regs.a = self.set(regs.a, Bit::Bit0); 2 },
 			0xCF => { self.regs.a = self.set(regs.a, Bit::Bit1); 2 },
@@ -662,14 +722,14 @@ impl CPU {
 			0xED => { self.regs.l = self.set(regs.l, Bit::Bit5); 2 },
 			0xF5 => { self.regs.l = self.set(regs.l, Bit::Bit6); 2 },
 			0xFD => { self.regs.l = self.set(regs.l, Bit::Bit7); 2 },
-			0xC6 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit0)); 4 },
-			0xCE => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit1)); 4 },
-			0xD6 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit2)); 4 },
-			0xDE => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit3)); 4 },
-			0xE6 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit4)); 4 },
-			0xEE => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit5)); 4 },
-			0xF6 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit6)); 4 },
-			0xFE => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.set(n, Bit::Bit7)); 4 },
+			0xC6 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit0)); 4 },
+			0xCE => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit1)); 4 },
+			0xD6 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit2)); 4 },
+			0xDE => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit3)); 4 },
+			0xE6 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit4)); 4 },
+			0xEE => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit5)); 4 },
+			0xF6 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit6)); 4 },
+			0xFE => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.set(n, Bit::Bit7)); 4 },
 			// Reset
 			0x87 => { self.regs.a = self.res(regs.a, Bit::Bit0); 2 },
 			0x8F => { self.regs.a = self.res(regs.a, Bit::Bit1); 2 },
@@ -727,31 +787,32 @@ impl CPU {
 			0xAD => { self.regs.l = self.res(regs.l, Bit::Bit5); 2 },
 			0xB5 => { self.regs.l = self.res(regs.l, Bit::Bit6); 2 },
 			0xBD => { self.regs.l = self.res(regs.l, Bit::Bit7); 2 },
-			0x86 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit0)); 4 },
-			0x8E => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit1)); 4 },
-			0x96 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit2)); 4 },
-			0x9E => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit3)); 4 },
-			0xA6 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit4)); 4 },
-			0xAE => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit5)); 4 },
-			0xB6 => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit6)); 4 },
-			0xBE => { let n = memory.read(regs.hl()); memory.write(regs.hl(), self.res(n, Bit::Bit7)); 4 },
+			0x86 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit0)); 4 },
+			0x8E => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit1)); 4 },
+			0x96 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit2)); 4 },
+			0x9E => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit3)); 4 },
+			0xA6 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit4)); 4 },
+			0xAE => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit5)); 4 },
+			0xB6 => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit6)); 4 },
+			0xBE => { let n = memory.read_cycle(regs.hl()); memory.write_cycle(regs.hl(), self.res(n, Bit::Bit7)); 4 },
 			_ => panic!("Unknown Sub-Op: ${:02X} @ ${:04X} dec: {}", opcode, pc, opcode)
 		}
 	}
 
-		// Pushes 16 bit data onto the stack
+	// Pushes 16 bit data onto the stack, after an internal cycle to decrement SP
 	fn push(&mut self, memory: &mut Interconnect, data: u16) {
+		memory.tick();
 		self.regs.sp = self.regs.sp.wrapping_sub(1);
-		memory.write(self.regs.sp, high!(data));
+		memory.write_cycle(self.regs.sp, high!(data));
 		self.regs.sp = self.regs.sp.wrapping_sub(1);
-		memory.write(self.regs.sp, low!(data));
+		memory.write_cycle(self.regs.sp, low!(data));
 	}
 
 	// Pops highest 16 bits from stack
 	fn pop(&mut self, memory: &mut Interconnect) -> u16 {
-		let low = memory.read(self.regs.sp);
+		let low = memory.read_cycle(self.regs.sp);
 		self.regs.sp = self.regs.sp.wrapping_add(1);
-		let high = memory.read(self.regs.sp);
+		let high = memory.read_cycle(self.regs.sp);
 		self.regs.sp = self.regs.sp.wrapping_add(1);
 		combine!(high, low)
 	}
@@ -1045,6 +1106,8 @@ impl CPU {
 
 	// Returns based on condition. Returns cycles (varied)
 	fn ret_if(&mut self, memory: &mut Interconnect, condition: Condition) -> usize {
+		// Checking the condition takes a cycle of its own
+		memory.tick();
 		match condition {
 			Condition::NotZero => { // CALL NZ
 				if !self.regs.is_flag_set(Flag::Zero) { self.ret(memory); 5 } else { 2 }
@@ -1083,10 +1146,33 @@ impl CPU {
 		self.regs.set_flag(Flag::Carry, (bit ^ 1) == 1);
 	}
 
-	fn handle_interrupts(&mut self, memory: &mut Interconnect) {
-		if let Some(vector) = memory.interrupt.execute_next() {
-			self.halted = false;
+	// Jumps to the next interrupt handler if IME is set, which takes 5 machine cycles
+	fn handle_interrupts(&mut self, memory: &mut Interconnect) -> Option<usize> {
+		memory.interrupt.execute_next().map(|vector| {
+			// After ei; halt the handler returns to the HALT, which runs again
+			if self.halt_bug {
+				self.halt_bug = false;
+				self.regs.pc = self.regs.pc.wrapping_sub(1);
+			}
+			memory.tick();
 			self.call(memory, vector as u16);
+			5
+		})
+	}
+
+	fn halt(&mut self, memory: &Interconnect) {
+		if !memory.interrupt.master_enabled() && memory.interrupt.pending() != 0 {
+			self.halt_bug = true;
+		} else {
+			self.halted = true;
+		}
+	}
+
+	// STOP is followed by a byte that is skipped. On CGB it also switches speed if one was prepared.
+	fn stop(&mut self, memory: &mut Interconnect) {
+		self.regs.pc = self.regs.pc.wrapping_add(1);
+		if !memory.stop() {
+			self.stopped = true;
 		}
 	}
 
@@ -1096,3 +1182,31 @@ impl CPU {
 		println!("{}", self.regs);
 	}
 }
+
+impl Stateful for CPU {
+	fn save_state(&self, state: &mut StateWriter) {
+		self.regs.save_state(state);
+		state.write_bool(self.halted);
+		state.write_bool(self.halt_bug);
+		state.write_bool(self.stopped);
+		state.write_bool(self.lock.is_some());
+		let (opcode, address) = self.lock.unwrap_or((0, 0));
+		state.write_u8(opcode);
+		state.write_u16(address);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.regs.load_state(state)?;
+		self.halted = state.read_bool()?;
+		self.halt_bug = state.read_bool()?;
+		self.stopped = state.read_bool()?;
+		let locked = state.read_bool()?;
+		let opcode = state.read_u8()?;
+		let address = state.read_u16()?;
+		self.lock = match locked {
+			true => Some((opcode, address)),
+			false => None,
+		};
+		Ok(())
+	}
+}
@@ -0,0 +1,223 @@
+use std::io::{BufRead, Write};
+use core::gameboy::*;
+use core::sink::*;
+use core::debugger::*;
+use core::debugger::expression::*;
+
+const HELP: &'static str = "Commands:
+  c, continue              resume execution
+  s, step [count]          step into the next instruction(s)
+  n, next                  step over calls
+  finish, out              run until the current routine returns
+  b, break <addr> [if <condition>]
+                           break at an address, e.g. b $150 if a == 3 && zf
+  w, watch <addr>[-<end>] [r|w|rw]
+                           stop on memory access, writes by default
+  del, delete <id>         remove a breakpoint or watchpoint
+  enable <id>, disable <id>
+  l, list                  list breakpoints and watchpoints
+  r, regs                  show registers
+  d, dis [addr] [count]    disassemble, from PC by default
+  x <addr> [count]         dump memory
+  p, print <expression>    evaluate an expression
+  q, quit                  stop the emulator
+An empty line repeats the last command.";
+
+pub enum ConsoleAction {
+	Continue,
+	Quit,
+}
+
+// Reads commands until execution should resume. Output errors are ignored, it's just a terminal.
+pub fn run_console<R: BufRead, W: Write>(debugger: &mut Debugger, gameboy: &mut GameBoy, input: &mut R, output: &mut W) -> ConsoleAction {
+	let mut video_sink = VideoSink::new();
+	let mut audio_sink = AudioSink::new();
+	let mut last_command = String::new();
+
+	show_location(debugger, gameboy, output);
+
+	loop {
+		let _ = write!(output, "(debug) ");
+		let _ = output.flush();
+
+		let mut line = String::new();
+		match input.read_line(&mut line) {
+			Ok(0) | Err(_) => return ConsoleAction::Quit,
+			Ok(_) => {},
+		}
+		let mut line = line.trim().to_owned();
+		if line.is_empty() {
+			line = last_command.clone();
+		}
+		last_command = line.clone();
+
+		let (command, arguments) = match line.find(' ') {
+			Some(index) => (&line[..index], line[index..].trim()),
+			None => (line.as_str(), ""),
+		};
+
+		let result = match command {
+			"" => Ok(()),
+			"h" | "help" => { let _ = writeln!(output, "{}", HELP); Ok(()) },
+			"c" | "continue" => return ConsoleAction::Continue,
+			"q" | "quit" => return ConsoleAction::Quit,
+			"s" | "step" => {
+				let count = match arguments {
+					"" => Ok(1),
+					_ => evaluate(gameboy, arguments),
+				};
+				count.map(|count| {
+					for _ in 0..count {
+						let (reason, _) = debugger.step(gameboy, &mut video_sink, &mut audio_sink);
+						if !report(reason, output) {
+							break;
+						}
+					}
+					show_location(debugger, gameboy, output);
+				})
+			},
+			"n" | "next" => {
+				let reason = debugger.step_over(gameboy, &mut video_sink, &mut audio_sink);
+				report(reason, output);
+				show_location(debugger, gameboy, output);
+				Ok(())
+			},
+			"finish" | "out" => {
+				let reason = debugger.step_out(gameboy, &mut video_sink, &mut audio_sink);
+				report(reason, output);
+				show_location(debugger, gameboy, output);
+				Ok(())
+			},
+			"b" | "break" => add_breakpoint(debugger, gameboy, arguments, output),
+			"w" | "watch" => add_watchpoint(debugger, gameboy, arguments, output),
+			"del" | "delete" => parse_id(arguments).and_then(|id| {
+				match debugger.remove(gameboy, id) {
+					true => Ok(()),
+					false => Err(format!("No breakpoint or watchpoint #{}", id)),
+				}
+			}),
+			"enable" | "disable" => parse_id(arguments).and_then(|id| {
+				match debugger.set_enabled(id, command == "enable") {
+					true => Ok(()),
+					false => Err(format!("No breakpoint #{}", id)),
+				}
+			}),
+			"l" | "list" => { list(debugger, output); Ok(()) },
+			"r" | "regs" => { let _ = writeln!(output, "{}", gameboy.cpu.regs); Ok(()) },
+			"d" | "dis" => {
+				let mut parts = arguments.split_whitespace();
+				let address = parts.next().map_or(Ok(gameboy.cpu.regs.pc), |a| evaluate(gameboy, a));
+				let count = parts.next().map_or(Ok(10), |c| evaluate(gameboy, c));
+				address.and_then(|address| count.map(|count| {
+					for (address, text) in debugger.disassemble(gameboy, address, count as usize) {
+						let _ = writeln!(output, "${:04X}: {}", address, text);
+					}
+				}))
+			},
+			"x" => {
+				let mut parts = arguments.split_whitespace();
+				let address = parts.next().map_or(Err("Missing address".to_owned()), |a| evaluate(gameboy, a));
+				let count = parts.next().map_or(Ok(16), |c| evaluate(gameboy, c));
+				address.and_then(|address| count.map(|count| dump(gameboy, address, count, output)))
+			},
+			"p" | "print" => evaluate(gameboy, arguments).map(|value| {
+				let _ = writeln!(output, "${:04X} ({})", value, value);
+			}),
+			_ => Err(format!("Unknown command: {}, try help", command)),
+		};
+
+		if let Err(message) = result {
+			let _ = writeln!(output, "{}", message);
+		}
+	}
+}
+
+fn evaluate(gameboy: &GameBoy, text: &str) -> Result<u16, String> {
+	Expression::parse(text).map(|expression| expression.evaluate(gameboy))
+}
+
+fn parse_id(text: &str) -> Result<usize, String> {
+	text.trim().parse::<usize>().map_err(|_| format!("Invalid id: {}", text))
+}
+
+// Prints why execution stopped, returns false if stepping should not continue
+fn report<W: Write>(reason: StopReason, output: &mut W) -> bool {
+	match reason {
+		StopReason::Step => true,
+		_ => { let _ = writeln!(output, "{}", reason); false },
+	}
+}
+
+fn show_location<W: Write>(debugger: &Debugger, gameboy: &GameBoy, output: &mut W) {
+	let pc = gameboy.cpu.regs.pc;
+	if let Some(&(address, ref text)) = debugger.disassemble(gameboy, pc, 1).first() {
+		let _ = writeln!(output, "${:04X}: {}", address, text);
+	}
+}
+
+fn add_breakpoint<W: Write>(debugger: &mut Debugger, gameboy: &GameBoy, arguments: &str, output: &mut W) -> Result<(), String> {
+	let (address, condition) = match arguments.find(" if ") {
+		Some(index) => (&arguments[..index], Some(&arguments[index + 4..])),
+		None => (arguments, None),
+	};
+	let address = evaluate(gameboy, address)?;
+	let condition = match condition {
+		Some(text) => Some(Expression::parse(text)?),
+		None => None,
+	};
+	let id = debugger.add_breakpoint(address, condition);
+	let _ = writeln!(output, "Breakpoint #{} at ${:04X}", id, address);
+	Ok(())
+}
+
+fn add_watchpoint<W: Write>(debugger: &mut Debugger, gameboy: &mut GameBoy, arguments: &str, output: &mut W) -> Result<(), String> {
+	let mut parts = arguments.split_whitespace();
+	let range = parts.next().ok_or("Missing address".to_owned())?;
+	let (start, end) = match range.find('-') {
+		Some(index) => (evaluate(gameboy, &range[..index])?, evaluate(gameboy, &range[index + 1..])?),
+		None => {
+			let address = evaluate(gameboy, range)?;
+			(address, address)
+		},
+	};
+	let access = match parts.next() {
+		None | Some("w") => Access::Write,
+		Some("r") => Access::Read,
+		Some("rw") => Access::ReadWrite,
+		Some(other) => return Err(format!("Unknown access: {}, use r, w or rw", other)),
+	};
+	let id = debugger.add_watchpoint(gameboy, start, end, access);
+	let _ = writeln!(output, "Watchpoint #{} on ${:04X}-${:04X} ({:?})", id, start, end, access);
+	Ok(())
+}
+
+fn list<W: Write>(debugger: &Debugger, output: &mut W) {
+	for breakpoint in debugger.breakpoints() {
+		let _ = write!(output, "#{} break ${:04X}", breakpoint.id, breakpoint.address);
+		if let Some(ref condition) = breakpoint.condition {
+			let _ = write!(output, " if {}", condition);
+		}
+		if !breakpoint.enabled {
+			let _ = write!(output, " (disabled)");
+		}
+		let _ = writeln!(output, "");
+	}
+	for watchpoint in debugger.watchpoints() {
+		let _ = writeln!(output, "#{} watch ${:04X}-${:04X} ({:?})",
+			watchpoint.id, watchpoint.start, watchpoint.end, watchpoint.access);
+	}
+}
+
+fn dump<W: Write>(gameboy: &GameBoy, address: u16, count: u16, output: &mut W) {
+	for row in 0..((count as usize + 15) / 16) {
+		let start = address.wrapping_add((row * 16) as u16);
+		let _ = write!(output, "${:04X}:", start);
+		for offset in 0..16 {
+			if row * 16 + offset >= count as usize {
+				break;
+			}
+			let _ = write!(output, " {:02X}", gameboy.interconnect.peek(start.wrapping_add(offset as u16)));
+		}
+		let _ = writeln!(output, "");
+	}
+}
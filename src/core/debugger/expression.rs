@@ -0,0 +1,324 @@
+use std::fmt;
+use core::gameboy::*;
+use core::register::*;
+
+// Conditions for breakpoints, e.g. "a == $10 && zf" or "[hl] != 0".
+// Registers: a f b c d e h l af bc de hl sp pc, flags: zf nf hf cf, memory: [address]
+// Numbers are decimal, or hexadecimal with a $ or 0x prefix. Anything non-zero is true.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+	A, F, B, C, D, E, H, L,
+	AF, BC, DE, HL, SP, PC,
+	ZeroFlag, SubFlag, HalfCarryFlag, CarryFlag,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+	Or, And,
+	Equal, NotEqual, Less, LessEqual, Greater, GreaterEqual,
+	Add, Sub, BitAnd, BitOr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+	Number(u16),
+	Register(Operand),
+	Memory(Box<Expression>),
+	Not(Box<Expression>),
+	Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Number(u16),
+	Name(String),
+	Operator(Operator),
+	Not,
+	Open,
+	Close,
+	OpenBracket,
+	CloseBracket,
+}
+
+impl Operand {
+	fn from_name(name: &str) -> Option<Operand> {
+		match name.to_lowercase().as_str() {
+			"a" => Some(Operand::A),
+			"f" => Some(Operand::F),
+			"b" => Some(Operand::B),
+			"c" => Some(Operand::C),
+			"d" => Some(Operand::D),
+			"e" => Some(Operand::E),
+			"h" => Some(Operand::H),
+			"l" => Some(Operand::L),
+			"af" => Some(Operand::AF),
+			"bc" => Some(Operand::BC),
+			"de" => Some(Operand::DE),
+			"hl" => Some(Operand::HL),
+			"sp" => Some(Operand::SP),
+			"pc" => Some(Operand::PC),
+			"zf" => Some(Operand::ZeroFlag),
+			"nf" => Some(Operand::SubFlag),
+			"hf" => Some(Operand::HalfCarryFlag),
+			"cf" => Some(Operand::CarryFlag),
+			_ => None,
+		}
+	}
+
+	fn name(&self) -> &'static str {
+		match *self {
+			Operand::A => "a",
+			Operand::F => "f",
+			Operand::B => "b",
+			Operand::C => "c",
+			Operand::D => "d",
+			Operand::E => "e",
+			Operand::H => "h",
+			Operand::L => "l",
+			Operand::AF => "af",
+			Operand::BC => "bc",
+			Operand::DE => "de",
+			Operand::HL => "hl",
+			Operand::SP => "sp",
+			Operand::PC => "pc",
+			Operand::ZeroFlag => "zf",
+			Operand::SubFlag => "nf",
+			Operand::HalfCarryFlag => "hf",
+			Operand::CarryFlag => "cf",
+		}
+	}
+
+	fn value(&self, regs: &Registers) -> u16 {
+		match *self {
+			Operand::A => regs.a as u16,
+			Operand::F => regs.f as u16,
+			Operand::B => regs.b as u16,
+			Operand::C => regs.c as u16,
+			Operand::D => regs.d as u16,
+			Operand::E => regs.e as u16,
+			Operand::H => regs.h as u16,
+			Operand::L => regs.l as u16,
+			Operand::AF => regs.af(),
+			Operand::BC => regs.bc(),
+			Operand::DE => regs.de(),
+			Operand::HL => regs.hl(),
+			Operand::SP => regs.sp,
+			Operand::PC => regs.pc,
+			Operand::ZeroFlag => regs.is_flag_set(Flag::Zero) as u16,
+			Operand::SubFlag => regs.is_flag_set(Flag::Sub) as u16,
+			Operand::HalfCarryFlag => regs.is_flag_set(Flag::HalfCarry) as u16,
+			Operand::CarryFlag => regs.is_flag_set(Flag::Carry) as u16,
+		}
+	}
+}
+
+// Parses a number in decimal, or hexadecimal with a $ or 0x prefix
+pub fn parse_number(text: &str) -> Option<u16> {
+	let text = text.trim();
+	if text.starts_with('$') {
+		u16::from_str_radix(&text[1..], 16).ok()
+	} else if text.starts_with("0x") || text.starts_with("0X") {
+		u16::from_str_radix(&text[2..], 16).ok()
+	} else {
+		text.parse::<u16>().ok()
+	}
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+	let chars: Vec<char> = text.chars().collect();
+	let mut tokens = Vec::new();
+	let mut i = 0;
+
+	while i < chars.len() {
+		let c = chars[i];
+		let next = chars.get(i + 1).cloned();
+		let (token, length) = match (c, next) {
+			(' ', _) | ('\t', _) => { i += 1; continue; },
+			('(', _) => (Token::Open, 1),
+			(')', _) => (Token::Close, 1),
+			('[', _) => (Token::OpenBracket, 1),
+			(']', _) => (Token::CloseBracket, 1),
+			('|', Some('|')) => (Token::Operator(Operator::Or), 2),
+			('&', Some('&')) => (Token::Operator(Operator::And), 2),
+			('=', Some('=')) => (Token::Operator(Operator::Equal), 2),
+			('!', Some('=')) => (Token::Operator(Operator::NotEqual), 2),
+			('<', Some('=')) => (Token::Operator(Operator::LessEqual), 2),
+			('>', Some('=')) => (Token::Operator(Operator::GreaterEqual), 2),
+			('<', _) => (Token::Operator(Operator::Less), 1),
+			('>', _) => (Token::Operator(Operator::Greater), 1),
+			('|', _) => (Token::Operator(Operator::BitOr), 1),
+			('&', _) => (Token::Operator(Operator::BitAnd), 1),
+			('+', _) => (Token::Operator(Operator::Add), 1),
+			('-', _) => (Token::Operator(Operator::Sub), 1),
+			('!', _) => (Token::Not, 1),
+			_ if c.is_alphanumeric() || c == '$' || c == '_' => {
+				let start = i;
+				let mut end = i + 1;
+				while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+					end += 1;
+				}
+				let word: String = chars[start..end].iter().collect();
+				let token = match c.is_digit(10) || c == '$' {
+					true => Token::Number(parse_number(&word).ok_or(format!("Invalid number: {}", word))?),
+					false => Token::Name(word),
+				};
+				(token, end - start)
+			},
+			_ => return Err(format!("Unexpected character: {}", c)),
+		};
+		tokens.push(token);
+		i += length;
+	}
+	Ok(tokens)
+}
+
+struct Parser {
+	tokens: Vec<Token>,
+	position: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.position).cloned();
+		self.position += 1;
+		token
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<(), String> {
+		match self.next() {
+			Some(ref token) if *token == expected => Ok(()),
+			_ => Err(format!("Expected {:?}", expected)),
+		}
+	}
+
+	// Binary operators grouped from lowest to highest precedence
+	fn binary(&mut self, level: usize) -> Result<Expression, String> {
+		let levels: [&[Operator]; 4] = [
+			&[Operator::Or],
+			&[Operator::And],
+			&[Operator::Equal, Operator::NotEqual, Operator::Less,
+			  Operator::LessEqual, Operator::Greater, Operator::GreaterEqual],
+			&[Operator::Add, Operator::Sub, Operator::BitAnd, Operator::BitOr],
+		];
+		if level == levels.len() {
+			return self.unary();
+		}
+
+		let mut left = self.binary(level + 1)?;
+		loop {
+			let operator = match self.peek() {
+				Some(&Token::Operator(operator)) if levels[level].contains(&operator) => operator,
+				_ => return Ok(left),
+			};
+			self.next();
+			let right = self.binary(level + 1)?;
+			left = Expression::Binary(operator, Box::new(left), Box::new(right));
+		}
+	}
+
+	fn unary(&mut self) -> Result<Expression, String> {
+		match self.next() {
+			Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+			Some(Token::Number(value)) => Ok(Expression::Number(value)),
+			Some(Token::Name(name)) => match Operand::from_name(&name) {
+				Some(operand) => Ok(Expression::Register(operand)),
+				None => Err(format!("Unknown register: {}", name)),
+			},
+			Some(Token::Open) => {
+				let expression = self.binary(0)?;
+				self.expect(Token::Close)?;
+				Ok(expression)
+			},
+			Some(Token::OpenBracket) => {
+				let address = self.binary(0)?;
+				self.expect(Token::CloseBracket)?;
+				Ok(Expression::Memory(Box::new(address)))
+			},
+			Some(token) => Err(format!("Unexpected {:?}", token)),
+			None => Err("Unexpected end of expression".to_owned()),
+		}
+	}
+}
+
+impl Expression {
+	pub fn parse(text: &str) -> Result<Expression, String> {
+		let mut parser = Parser {
+			tokens: tokenize(text)?,
+			position: 0,
+		};
+		let expression = parser.binary(0)?;
+		match parser.peek() {
+			None => Ok(expression),
+			Some(token) => Err(format!("Unexpected {:?}", token)),
+		}
+	}
+
+	pub fn evaluate(&self, gameboy: &GameBoy) -> u16 {
+		match *self {
+			Expression::Number(value) => value,
+			Expression::Register(operand) => operand.value(&gameboy.cpu.regs),
+			Expression::Memory(ref address) => gameboy.interconnect.peek(address.evaluate(gameboy)) as u16,
+			Expression::Not(ref value) => (value.evaluate(gameboy) == 0) as u16,
+			Expression::Binary(operator, ref left, ref right) => {
+				let left = left.evaluate(gameboy);
+				// Short circuit so memory isn't read needlessly
+				match operator {
+					Operator::Or if left != 0 => return 1,
+					Operator::And if left == 0 => return 0,
+					_ => {},
+				}
+				let right = right.evaluate(gameboy);
+				match operator {
+					Operator::Or | Operator::And => (right != 0) as u16,
+					Operator::Equal => (left == right) as u16,
+					Operator::NotEqual => (left != right) as u16,
+					Operator::Less => (left < right) as u16,
+					Operator::LessEqual => (left <= right) as u16,
+					Operator::Greater => (left > right) as u16,
+					Operator::GreaterEqual => (left >= right) as u16,
+					Operator::Add => left.wrapping_add(right),
+					Operator::Sub => left.wrapping_sub(right),
+					Operator::BitAnd => left & right,
+					Operator::BitOr => left | right,
+				}
+			},
+		}
+	}
+
+	pub fn is_true(&self, gameboy: &GameBoy) -> bool {
+		self.evaluate(gameboy) != 0
+	}
+}
+
+impl fmt::Display for Expression {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Expression::Number(value) => write!(f, "${:X}", value),
+			Expression::Register(operand) => write!(f, "{}", operand.name()),
+			Expression::Memory(ref address) => write!(f, "[{}]", address),
+			Expression::Not(ref value) => write!(f, "!{}", value),
+			Expression::Binary(operator, ref left, ref right) => {
+				let symbol = match operator {
+					Operator::Or => "||",
+					Operator::And => "&&",
+					Operator::Equal => "==",
+					Operator::NotEqual => "!=",
+					Operator::Less => "<",
+					Operator::LessEqual => "<=",
+					Operator::Greater => ">",
+					Operator::GreaterEqual => ">=",
+					Operator::Add => "+",
+					Operator::Sub => "-",
+					Operator::BitAnd => "&",
+					Operator::BitOr => "|",
+				};
+				write!(f, "({} {} {})", left, symbol, right)
+			},
+		}
+	}
+}
@@ -0,0 +1,399 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use core::gameboy::*;
+use core::sink::*;
+use core::debugger::*;
+use core::error::*;
+
+// GDB remote serial protocol server.
+// There is no official GDB target for the SM83, so registers are sent as
+// six 16-bit little endian values: AF, BC, DE, HL, SP, PC.
+const REGISTER_COUNT: usize = 6;
+// Continuing runs this many cycles between checks for an interrupt from the client
+const RUN_CYCLES: usize = CYCLES_PER_FRAME;
+const SIGTRAP: &'static str = "S05";
+const SIGILL: &'static str = "S04";
+const INTERRUPT: u8 = 0x03;
+// Largest packet the client may send or expect back, advertised in qSupported
+const PACKET_SIZE: usize = 0x1000;
+// Memory reads reply with two hex digits per byte, which must fit in a packet
+const MAX_MEMORY_LENGTH: usize = (PACKET_SIZE - 1) / 2;
+
+pub struct GdbStub {
+	stream: TcpStream,
+	// Breakpoints set by the client, by address with their debugger id
+	breakpoints: Vec<(u16, usize)>,
+	watchpoints: Vec<(u16, Access, usize)>,
+	no_ack: bool,
+	video_sink: VideoSink,
+	audio_sink: AudioSink,
+}
+
+impl GdbStub {
+	// Waits for a client to connect
+	pub fn listen<A: ToSocketAddrs>(address: A) -> io::Result<GdbStub> {
+		let listener = TcpListener::bind(address)?;
+		let (stream, _) = listener.accept()?;
+		stream.set_nodelay(true)?;
+		Ok(GdbStub::new(stream))
+	}
+
+	pub fn new(stream: TcpStream) -> GdbStub {
+		GdbStub {
+			stream: stream,
+			breakpoints: Vec::new(),
+			watchpoints: Vec::new(),
+			no_ack: false,
+			video_sink: VideoSink::new(),
+			audio_sink: AudioSink::new(),
+		}
+	}
+
+	// Handles packets until the client detaches, kills the target or disconnects
+	pub fn serve(&mut self, debugger: &mut Debugger, gameboy: &mut GameBoy) -> io::Result<()> {
+		loop {
+			let packet = match self.read_packet()? {
+				Some(packet) => packet,
+				None => return Ok(()),
+			};
+			let reply = match packet.chars().next() {
+				Some('D') => { self.write_packet("OK")?; return Ok(()); },
+				Some('k') => return Ok(()),
+				_ => self.handle(&packet, debugger, gameboy)?,
+			};
+			self.write_packet(&reply)?;
+		}
+	}
+
+	fn handle(&mut self, packet: &str, debugger: &mut Debugger, gameboy: &mut GameBoy) -> io::Result<String> {
+		if packet.is_empty() {
+			return Ok(String::new());
+		}
+		// Commands and their arguments are plain ASCII, anything else is garbage
+		if !packet.is_ascii() {
+			return Ok("E01".to_owned());
+		}
+		let (command, arguments) = packet.split_at(1);
+		let reply = match command {
+			"?" => SIGTRAP.to_owned(),
+			"g" => {
+				let regs = &gameboy.cpu.regs;
+				let values = [regs.af(), regs.bc(), regs.de(), regs.hl(), regs.sp, regs.pc];
+				values.iter().map(|&value| encode_word(value)).collect()
+			},
+			"G" => match decode_bytes(arguments) {
+				Some(ref bytes) if bytes.len() == REGISTER_COUNT * 2 => {
+					for register in 0..REGISTER_COUNT {
+						let value = (bytes[register * 2] as u16) | ((bytes[register * 2 + 1] as u16) << 8);
+						set_register(gameboy, register, value);
+					}
+					"OK".to_owned()
+				},
+				_ => "E01".to_owned(),
+			},
+			"p" => match usize::from_str_radix(arguments, 16) {
+				Ok(register) if register < REGISTER_COUNT => encode_word(get_register(gameboy, register)),
+				_ => "E01".to_owned(),
+			},
+			"P" => {
+				let mut parts = arguments.splitn(2, '=');
+				let register = parts.next().and_then(|r| usize::from_str_radix(r, 16).ok());
+				let value = parts.next().and_then(decode_bytes);
+				match (register, value) {
+					(Some(register), Some(ref bytes)) if register < REGISTER_COUNT && bytes.len() == 2 => {
+						set_register(gameboy, register, (bytes[0] as u16) | ((bytes[1] as u16) << 8));
+						"OK".to_owned()
+					},
+					_ => "E01".to_owned(),
+				}
+			},
+			"m" => match parse_address_length(arguments) {
+				Some((address, length)) => (0..length)
+					.map(|offset| format!("{:02x}", gameboy.interconnect.peek(address.wrapping_add(offset as u16))))
+					.collect(),
+				None => "E01".to_owned(),
+			},
+			"M" => {
+				let mut parts = arguments.splitn(2, ':');
+				let range = parts.next().and_then(parse_address_length);
+				let data = parts.next().and_then(decode_bytes);
+				match (range, data) {
+					(Some((address, length)), Some(ref bytes)) if bytes.len() == length => {
+						for (offset, &byte) in bytes.iter().enumerate() {
+							gameboy.interconnect.write(address.wrapping_add(offset as u16), byte);
+						}
+						"OK".to_owned()
+					},
+					_ => "E01".to_owned(),
+				}
+			},
+			"s" => {
+				self.resume_at(arguments, gameboy);
+				let (reason, _) = debugger.step(gameboy, &mut self.video_sink, &mut self.audio_sink);
+				self.stop_reply(reason)
+			},
+			"c" => {
+				self.resume_at(arguments, gameboy);
+				self.continue_execution(debugger, gameboy)?
+			},
+			"Z" | "z" => self.breakpoint(command == "Z", arguments, debugger, gameboy),
+			"H" => "OK".to_owned(),
+			"T" => "OK".to_owned(),
+			"q" | "Q" => self.query(packet),
+			// Anything else isn't supported, which is an empty reply
+			_ => String::new(),
+		};
+		Ok(reply)
+	}
+
+	fn query(&mut self, packet: &str) -> String {
+		match packet {
+			_ if packet.starts_with("qSupported") => format!("PacketSize={:x};QStartNoAckMode+", PACKET_SIZE),
+			"QStartNoAckMode" => {
+				self.no_ack = true;
+				"OK".to_owned()
+			},
+			"qAttached" => "1".to_owned(),
+			"qC" => "QC1".to_owned(),
+			"qfThreadInfo" => "m1".to_owned(),
+			"qsThreadInfo" => "l".to_owned(),
+			_ => String::new(),
+		}
+	}
+
+	// c and s may give an address to resume from
+	fn resume_at(&self, arguments: &str, gameboy: &mut GameBoy) {
+		if let Ok(address) = u16::from_str_radix(arguments, 16) {
+			gameboy.cpu.regs.pc = address;
+		}
+	}
+
+	fn continue_execution(&mut self, debugger: &mut Debugger, gameboy: &mut GameBoy) -> io::Result<String> {
+		loop {
+			let (reason, _) = debugger.run(gameboy, &mut self.video_sink, &mut self.audio_sink, RUN_CYCLES);
+			// Nobody is watching, throw away what was produced
+			self.video_sink = VideoSink::new();
+			self.audio_sink = AudioSink::new();
+			if let StopReason::Step = reason {} else {
+				return Ok(self.stop_reply(reason));
+			}
+			// Ctrl+C in the client sends a single interrupt byte
+			if self.interrupted()? {
+				return Ok(SIGTRAP.to_owned());
+			}
+		}
+	}
+
+	fn interrupted(&mut self) -> io::Result<bool> {
+		self.stream.set_nonblocking(true)?;
+		let mut byte = [0];
+		let result = match self.stream.read(&mut byte) {
+			Ok(1) => Ok(byte[0] == INTERRUPT),
+			Ok(_) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Client disconnected")),
+			Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+			Err(e) => Err(e),
+		};
+		self.stream.set_nonblocking(false)?;
+		result
+	}
+
+	fn stop_reply(&self, reason: StopReason) -> String {
+		match reason {
+			StopReason::Watchpoint(hit) => {
+				// Report the watchpoint the way it was set by the client
+				let (address, access) = self.watchpoints.iter()
+					.find(|&&(_, _, id)| id == hit.id)
+					.map_or((hit.address, hit.access), |&(address, access, _)| (address, access));
+				let kind = match access {
+					Access::Read => "rwatch",
+					Access::Write => "watch",
+					Access::ReadWrite => "awatch",
+				};
+				format!("T05{}:{:x};", kind, address)
+			},
+			// A locked up CPU is reported as an illegal instruction
+			StopReason::Error(EmuError::CpuLocked { .. }) => SIGILL.to_owned(),
+			_ => SIGTRAP.to_owned(),
+		}
+	}
+
+	// Z/z type,address,kind. Type 0/1 are breakpoints, 2-4 are write/read/access watchpoints.
+	fn breakpoint(&mut self, insert: bool, arguments: &str, debugger: &mut Debugger, gameboy: &mut GameBoy) -> String {
+		let parts: Vec<&str> = arguments.split(',').collect();
+		if parts.len() < 2 {
+			return "E01".to_owned();
+		}
+		let address = match u16::from_str_radix(parts[1], 16) {
+			Ok(address) => address,
+			Err(_) => return "E01".to_owned(),
+		};
+		let access = match parts[0] {
+			"0" | "1" => None,
+			"2" => Some(Access::Write),
+			"3" => Some(Access::Read),
+			"4" => Some(Access::ReadWrite),
+			_ => return String::new(),
+		};
+
+		match (insert, access) {
+			(true, None) => {
+				let id = debugger.add_breakpoint(address, None);
+				self.breakpoints.push((address, id));
+			},
+			(true, Some(access)) => {
+				let length = parts.get(2).and_then(|l| u16::from_str_radix(l, 16).ok()).unwrap_or(1);
+				let end = address.wrapping_add(length.max(1) - 1);
+				let id = debugger.add_watchpoint(gameboy, address, end, access);
+				self.watchpoints.push((address, access, id));
+			},
+			(false, None) => {
+				if let Some(index) = self.breakpoints.iter().position(|&(a, _)| a == address) {
+					let (_, id) = self.breakpoints.remove(index);
+					debugger.remove(gameboy, id);
+				}
+			},
+			(false, Some(access)) => {
+				if let Some(index) = self.watchpoints.iter().position(|&(a, k, _)| a == address && k == access) {
+					let (_, _, id) = self.watchpoints.remove(index);
+					debugger.remove(gameboy, id);
+				}
+			},
+		}
+		"OK".to_owned()
+	}
+
+	// Returns None once the client has disconnected
+	fn read_packet(&mut self) -> io::Result<Option<String>> {
+		let mut byte = [0];
+		loop {
+			// Skip acknowledgements and anything else until the start of a packet
+			loop {
+				if self.stream.read(&mut byte)? == 0 {
+					return Ok(None);
+				}
+				if byte[0] == b'$' {
+					break;
+				}
+			}
+
+			let mut data = Vec::new();
+			let mut too_long = false;
+			loop {
+				if self.stream.read(&mut byte)? == 0 {
+					return Ok(None);
+				}
+				if byte[0] == b'#' {
+					break;
+				}
+				// Packets larger than advertised are read to the end and rejected
+				match data.len() < PACKET_SIZE {
+					true => data.push(byte[0]),
+					false => too_long = true,
+				}
+			}
+			let mut checksum = [0; 2];
+			self.stream.read_exact(&mut checksum)?;
+
+			let expected = String::from_utf8_lossy(&checksum);
+			let valid = !too_long && u8::from_str_radix(&expected, 16).ok() == Some(packet_checksum(&data));
+			if !self.no_ack {
+				self.stream.write_all(if valid { b"+" } else { b"-" })?;
+			} else if too_long {
+				// Without acknowledgements the client would wait for a reply forever
+				self.write_packet("E01")?;
+			}
+			if valid {
+				return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+			}
+		}
+	}
+
+	fn write_packet(&mut self, data: &str) -> io::Result<()> {
+		let escaped = escape(data.as_bytes());
+		let mut packet = Vec::with_capacity(escaped.len() + 4);
+		packet.push(b'$');
+		packet.extend_from_slice(&escaped);
+		packet.extend_from_slice(format!("#{:02x}", packet_checksum(&escaped)).as_bytes());
+		self.stream.write_all(&packet)?;
+		self.stream.flush()
+	}
+}
+
+fn get_register(gameboy: &GameBoy, register: usize) -> u16 {
+	let regs = &gameboy.cpu.regs;
+	match register {
+		0 => regs.af(),
+		1 => regs.bc(),
+		2 => regs.de(),
+		3 => regs.hl(),
+		4 => regs.sp,
+		_ => regs.pc,
+	}
+}
+
+fn set_register(gameboy: &mut GameBoy, register: usize, value: u16) {
+	let regs = &mut gameboy.cpu.regs;
+	match register {
+		// The low nibble of F always reads back as zero
+		0 => regs.set_af(value & 0xFFF0),
+		1 => regs.set_bc(value),
+		2 => regs.set_de(value),
+		3 => regs.set_hl(value),
+		4 => regs.sp = value,
+		_ => regs.pc = value,
+	}
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+	data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+// '#', '$', '}' and '*' are sent as '}' followed by the byte xor 0x20
+fn escape(data: &[u8]) -> Vec<u8> {
+	let mut escaped = Vec::with_capacity(data.len());
+	for &byte in data {
+		match byte {
+			b'#' | b'$' | b'}' | b'*' => { escaped.push(b'}'); escaped.push(byte ^ 0x20); },
+			_ => escaped.push(byte),
+		}
+	}
+	escaped
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+	let mut unescaped = Vec::with_capacity(data.len());
+	let mut bytes = data.iter();
+	while let Some(&byte) = bytes.next() {
+		match byte {
+			b'}' => if let Some(&next) = bytes.next() { unescaped.push(next ^ 0x20); },
+			_ => unescaped.push(byte),
+		}
+	}
+	unescaped
+}
+
+fn encode_word(value: u16) -> String {
+	format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+fn decode_bytes(text: &str) -> Option<Vec<u8>> {
+	// Slicing by byte index is only safe when every character is a single byte
+	if text.len() % 2 != 0 || !text.is_ascii() {
+		return None;
+	}
+	(0..text.len() / 2)
+		.map(|i| u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok())
+		.collect()
+}
+
+fn parse_address_length(text: &str) -> Option<(u16, usize)> {
+	let mut parts = text.splitn(2, ',');
+	let address = parts.next().and_then(|a| u16::from_str_radix(a, 16).ok())?;
+	let length = parts.next().and_then(|l| usize::from_str_radix(l, 16).ok())?;
+	// Also keeps the range within the 16-bit address space
+	match length <= MAX_MEMORY_LENGTH {
+		true => Some((address, length)),
+		false => None,
+	}
+}
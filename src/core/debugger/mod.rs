@@ -0,0 +1,271 @@
+pub mod expression;
+pub mod console;
+pub mod gdb;
+
+use std::fmt;
+use core::gameboy::*;
+use core::sink::*;
+use core::error::*;
+use core::disassembler::*;
+use core::disassembler::decoder::*;
+use self::expression::*;
+
+// Stepping over or out of a routine gives up after this many instructions
+pub const STEP_LIMIT: usize = 10_000_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+	Read,
+	Write,
+	ReadWrite,
+}
+
+// Watches a range of memory, start and end inclusive
+#[derive(Debug, Copy, Clone)]
+pub struct Watchpoint {
+	pub id: usize,
+	pub start: u16,
+	pub end: u16,
+	pub access: Access,
+}
+
+impl Watchpoint {
+	pub fn matches(&self, address: u16, access: Access) -> bool {
+		let in_range = address >= self.start && address <= self.end;
+		in_range && (self.access == Access::ReadWrite || self.access == access)
+	}
+}
+
+// A memory access caught by a watchpoint
+#[derive(Debug, Copy, Clone)]
+pub struct WatchHit {
+	pub id: usize,
+	pub address: u16,
+	pub value: u8,
+	pub access: Access,
+}
+
+pub struct Breakpoint {
+	pub id: usize,
+	pub address: u16,
+	pub condition: Option<Expression>,
+	pub enabled: bool,
+}
+
+#[derive(Debug)]
+pub enum StopReason {
+	// An instruction was executed with nothing else to report
+	Step,
+	Breakpoint(usize),
+	Watchpoint(WatchHit),
+	// Stepping over/out gave up, the routine never returned
+	Limit,
+	// The machine can't go on, e.g. the CPU locked up
+	Error(EmuError),
+}
+
+impl fmt::Display for StopReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			StopReason::Step => write!(f, "Step"),
+			StopReason::Breakpoint(id) => write!(f, "Breakpoint #{}", id),
+			StopReason::Watchpoint(hit) => write!(f, "Watchpoint #{}: {:?} ${:02X} at ${:04X}",
+				hit.id, hit.access, hit.value, hit.address),
+			StopReason::Limit => write!(f, "Gave up after {} instructions", STEP_LIMIT),
+			StopReason::Error(ref e) => write!(f, "{}", e),
+		}
+	}
+}
+
+pub struct Debugger {
+	breakpoints: Vec<Breakpoint>,
+	watchpoints: Vec<Watchpoint>,
+	next_id: usize,
+	// Address of the breakpoint reported last, so resuming from it doesn't stop again right away
+	stopped_at: Option<u16>,
+}
+
+impl Debugger {
+	pub fn new() -> Debugger {
+		Debugger {
+			breakpoints: Vec::new(),
+			watchpoints: Vec::new(),
+			next_id: 1,
+			stopped_at: None,
+		}
+	}
+
+	pub fn breakpoints(&self) -> &Vec<Breakpoint> {
+		&self.breakpoints
+	}
+
+	pub fn watchpoints(&self) -> &Vec<Watchpoint> {
+		&self.watchpoints
+	}
+
+	// Stops before the instruction at address is executed, if the condition holds
+	pub fn add_breakpoint(&mut self, address: u16, condition: Option<Expression>) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.breakpoints.push(Breakpoint {
+			id: id,
+			address: address,
+			condition: condition,
+			enabled: true,
+		});
+		id
+	}
+
+	pub fn add_watchpoint(&mut self, gameboy: &mut GameBoy, start: u16, end: u16, access: Access) -> usize {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.watchpoints.push(Watchpoint {
+			id: id,
+			start: start,
+			end: end,
+			access: access,
+		});
+		gameboy.interconnect.set_watchpoints(self.watchpoints.clone());
+		id
+	}
+
+	// Removes a breakpoint or watchpoint
+	pub fn remove(&mut self, gameboy: &mut GameBoy, id: usize) -> bool {
+		let count = self.breakpoints.len() + self.watchpoints.len();
+		self.breakpoints.retain(|b| b.id != id);
+		self.watchpoints.retain(|w| w.id != id);
+		gameboy.interconnect.set_watchpoints(self.watchpoints.clone());
+		count != self.breakpoints.len() + self.watchpoints.len()
+	}
+
+	pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+		match self.breakpoints.iter_mut().find(|b| b.id == id) {
+			Some(breakpoint) => { breakpoint.enabled = enabled; true },
+			None => false,
+		}
+	}
+
+	fn breakpoint_hit(&self, gameboy: &GameBoy) -> Option<usize> {
+		let pc = gameboy.cpu.regs.pc;
+		self.breakpoints.iter()
+			.filter(|b| b.enabled && b.address == pc)
+			.find(|b| match b.condition {
+				Some(ref condition) => condition.is_true(gameboy),
+				None => true,
+			})
+			.map(|b| b.id)
+	}
+
+	// Executes one instruction and reports any breakpoint at the next one or memory access that was watched.
+	// A breakpoint at the current instruction that hasn't been reported yet stops before anything runs.
+	pub fn step(&mut self, gameboy: &mut GameBoy, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) -> (StopReason, usize) {
+		if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+			self.stopped_at = None;
+			return match gameboy.step(video_sink, audio_sink) {
+				Ok(cycles) => (StopReason::Step, cycles),
+				Err(e) => (StopReason::Error(e), 0),
+			};
+		}
+
+		let pc = gameboy.cpu.regs.pc;
+		if self.stopped_at != Some(pc) {
+			if let Some(id) = self.breakpoint_hit(gameboy) {
+				self.stopped_at = Some(pc);
+				return (StopReason::Breakpoint(id), 0);
+			}
+		}
+		self.stopped_at = None;
+
+		gameboy.interconnect.take_watch_hits();
+		let cycles = match gameboy.step(video_sink, audio_sink) {
+			Ok(cycles) => cycles,
+			Err(e) => return (StopReason::Error(e), 0),
+		};
+
+		if let Some(hit) = gameboy.interconnect.take_watch_hits().first() {
+			return (StopReason::Watchpoint(*hit), cycles);
+		}
+		match self.breakpoint_hit(gameboy) {
+			Some(id) => {
+				self.stopped_at = Some(gameboy.cpu.regs.pc);
+				(StopReason::Breakpoint(id), cycles)
+			},
+			None => (StopReason::Step, cycles),
+		}
+	}
+
+	// Runs until a breakpoint or watchpoint stops execution, or the cycle budget runs out
+	pub fn run(&mut self, gameboy: &mut GameBoy, video_sink: &mut VideoSink, audio_sink: &mut AudioSink, max_cycles: usize) -> (StopReason, usize) {
+		let mut cycles = 0;
+		while cycles < max_cycles {
+			let (reason, taken) = self.step(gameboy, video_sink, audio_sink);
+			cycles += taken;
+			match reason {
+				StopReason::Step => {},
+				_ => return (reason, cycles),
+			}
+		}
+		(StopReason::Step, cycles)
+	}
+
+	// Steps over calls and restarts, running the whole routine as one instruction
+	pub fn step_over(&mut self, gameboy: &mut GameBoy, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) -> StopReason {
+		let pc = gameboy.cpu.regs.pc;
+		let instruction = decode_at(&gameboy.interconnect, pc);
+		if instruction.flow != Flow::Call {
+			return self.step(gameboy, video_sink, audio_sink).0;
+		}
+
+		let return_address = pc.wrapping_add(instruction.length);
+		let sp = gameboy.cpu.regs.sp;
+		self.run_until(gameboy, video_sink, audio_sink, |gameboy, _| {
+			// SP guards against recursive calls reaching the same address deeper down
+			gameboy.cpu.regs.pc == return_address && gameboy.cpu.regs.sp >= sp
+		})
+	}
+
+	// Runs until the current routine returns to its caller
+	pub fn step_out(&mut self, gameboy: &mut GameBoy, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) -> StopReason {
+		let sp = gameboy.cpu.regs.sp;
+		self.run_until(gameboy, video_sink, audio_sink, |gameboy, opcode| {
+			is_return(opcode) && gameboy.cpu.regs.sp > sp
+		})
+	}
+
+	// Steps until done returns true for the machine and the opcode just executed
+	fn run_until<F>(&mut self, gameboy: &mut GameBoy, video_sink: &mut VideoSink, audio_sink: &mut AudioSink, done: F) -> StopReason
+		where F: Fn(&GameBoy, u8) -> bool {
+		for _ in 0..STEP_LIMIT {
+			let opcode = gameboy.interconnect.peek(gameboy.cpu.regs.pc);
+			let (reason, _) = self.step(gameboy, video_sink, audio_sink);
+			if done(gameboy, opcode) {
+				return StopReason::Step;
+			}
+			match reason {
+				StopReason::Step => {},
+				_ => return reason,
+			}
+		}
+		StopReason::Limit
+	}
+
+	// Disassembles count instructions starting at address
+	pub fn disassemble(&self, gameboy: &GameBoy, address: u16, count: usize) -> Vec<(u16, String)> {
+		let mut lines = Vec::new();
+		let mut address = address;
+		for _ in 0..count {
+			let instruction = decode_at(&gameboy.interconnect, address);
+			lines.push((address, instruction.to_string()));
+			address = address.wrapping_add(instruction.length);
+		}
+		lines
+	}
+}
+
+// RET, RET cc and RETI
+fn is_return(opcode: u8) -> bool {
+	match opcode {
+		0xC9 | 0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xD9 => true,
+		_ => false,
+	}
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+
+// Decodes SM83 instructions from raw bytes, without needing a running machine.
+// Cycles are counted in machine cycles, like CPU::step returns them.
+// Output follows RGBDS syntax so listings can be assembled again.
+
+const REGISTERS: [&'static str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const REGISTER_PAIRS: [&'static str; 4] = ["bc", "de", "hl", "sp"];
+const STACK_PAIRS: [&'static str; 4] = ["bc", "de", "hl", "af"];
+const CONDITIONS: [&'static str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&'static str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const ROTATIONS: [&'static str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+	// a, b, hl, sp...
+	Register(&'static str),
+	// [hl], [bc], [hl+], [c]...
+	Indirect(&'static str),
+	Immediate8(u8),
+	Immediate16(u16),
+	// [nn]
+	Address(u16),
+	// [$FF00+n], used by ldh
+	HighAddress(u8),
+	// Destination of a jump or call
+	Target(u16),
+	Condition(&'static str),
+	Bit(u8),
+	// Signed offset for add sp, e
+	Offset(i8),
+	// sp+e for ld hl, sp+e
+	StackOffset(i8),
+}
+
+// How an instruction affects the flow of execution
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Flow {
+	// Continues with the next instruction
+	Next,
+	// Always continues at the target
+	Jump,
+	// Continues at the target or the next instruction
+	Branch,
+	// Continues at the target and comes back to the next instruction
+	Call,
+	// Continues somewhere only known at runtime: ret, reti, jp hl
+	Return,
+	// Continues at the next instruction or returns
+	ConditionalReturn,
+	// Not a valid opcode, the CPU locks up
+	Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+	pub opcode: u8,
+	pub mnemonic: &'static str,
+	pub operands: Vec<Operand>,
+	pub length: u16,
+	// Machine cycles, when a conditional branch is not taken
+	pub cycles: u8,
+	// Machine cycles when a conditional branch is taken
+	pub cycles_taken: u8,
+	pub flow: Flow,
+	// Where a jump, call or restart goes
+	pub target: Option<u16>,
+}
+
+impl Instruction {
+	fn new(opcode: u8, mnemonic: &'static str, operands: Vec<Operand>, length: u16, cycles: u8) -> Instruction {
+		Instruction {
+			opcode: opcode,
+			mnemonic: mnemonic,
+			operands: operands,
+			length: length,
+			cycles: cycles,
+			cycles_taken: cycles,
+			flow: Flow::Next,
+			target: None,
+		}
+	}
+
+	fn flow(mut self, flow: Flow, target: Option<u16>) -> Instruction {
+		self.flow = flow;
+		self.target = target;
+		self
+	}
+
+	fn taken(mut self, cycles: u8) -> Instruction {
+		self.cycles_taken = cycles;
+		self
+	}
+
+	// Formats the instruction, asking labels for a name to use instead of each target address
+	pub fn format_with<F: Fn(u16) -> Option<String>>(&self, labels: F) -> String {
+		if self.flow == Flow::Invalid {
+			return format!("db ${:02X}", self.opcode);
+		}
+		let operands: Vec<String> = self.operands.iter().map(|operand| match *operand {
+			Operand::Target(address) => labels(address).unwrap_or(format!("${:04X}", address)),
+			_ => operand.to_string(),
+		}).collect();
+		match operands.is_empty() {
+			true => self.mnemonic.to_owned(),
+			false => format!("{} {}", self.mnemonic, operands.join(", ")),
+		}
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Operand::Register(name) | Operand::Condition(name) => write!(f, "{}", name),
+			Operand::Indirect(name) => write!(f, "[{}]", name),
+			Operand::Immediate8(value) => write!(f, "${:02X}", value),
+			Operand::Immediate16(value) => write!(f, "${:04X}", value),
+			Operand::Address(address) => write!(f, "[${:04X}]", address),
+			Operand::HighAddress(offset) => write!(f, "[$FF{:02X}]", offset),
+			Operand::Target(address) => write!(f, "${:04X}", address),
+			Operand::Bit(bit) => write!(f, "{}", bit),
+			Operand::Offset(offset) => write!(f, "{}", offset),
+			Operand::StackOffset(offset) if offset < 0 => write!(f, "sp-{}", -(offset as i16)),
+			Operand::StackOffset(offset) => write!(f, "sp+{}", offset),
+		}
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.format_with(|_| None))
+	}
+}
+
+// Register operand, (HL) is an indirect access
+fn register(index: u8) -> Operand {
+	match index {
+		6 => Operand::Indirect("hl"),
+		_ => Operand::Register(REGISTERS[index as usize]),
+	}
+}
+
+// Decodes the instruction at the start of bytes, which was read from address.
+// Returns None if bytes ends before the instruction does.
+pub fn decode(bytes: &[u8], address: u16) -> Option<Instruction> {
+	use self::Operand::*;
+
+	let opcode = *bytes.get(0)?;
+	let n = || bytes.get(1).cloned();
+	let nn = || match (bytes.get(1), bytes.get(2)) {
+		(Some(&low), Some(&high)) => Some(((high as u16) << 8) | low as u16),
+		_ => None,
+	};
+	let relative = |offset: u8| address.wrapping_add(2).wrapping_add(offset as i8 as u16);
+
+	let x = opcode >> 6;
+	let y = (opcode >> 3) & 7;
+	let z = opcode & 7;
+	let p = (y >> 1) as usize;
+	let q = y & 1;
+	let i = Instruction::new;
+
+	let instruction = match (x, z) {
+		(0, 0) => match y {
+			0 => i(opcode, "nop", vec![], 1, 1),
+			1 => i(opcode, "ld", vec![Address(nn()?), Register("sp")], 3, 5),
+			2 => i(opcode, "stop", vec![], 2, 1),
+			3 => {
+				let target = relative(n()?);
+				i(opcode, "jr", vec![Target(target)], 2, 3).flow(Flow::Jump, Some(target))
+			},
+			_ => {
+				let target = relative(n()?);
+				i(opcode, "jr", vec![Condition(CONDITIONS[(y - 4) as usize]), Target(target)], 2, 2)
+					.taken(3).flow(Flow::Branch, Some(target))
+			},
+		},
+		(0, 1) => match q {
+			0 => i(opcode, "ld", vec![Register(REGISTER_PAIRS[p]), Immediate16(nn()?)], 3, 3),
+			_ => i(opcode, "add", vec![Register("hl"), Register(REGISTER_PAIRS[p])], 1, 2),
+		},
+		(0, 2) => {
+			let memory = Indirect(["bc", "de", "hl+", "hl-"][p]);
+			match q {
+				0 => i(opcode, "ld", vec![memory, Register("a")], 1, 2),
+				_ => i(opcode, "ld", vec![Register("a"), memory], 1, 2),
+			}
+		},
+		(0, 3) => i(opcode, ["inc", "dec"][q as usize], vec![Register(REGISTER_PAIRS[p])], 1, 2),
+		(0, 4) | (0, 5) => {
+			let cycles = if y == 6 { 3 } else { 1 };
+			i(opcode, ["inc", "dec"][(z - 4) as usize], vec![register(y)], 1, cycles)
+		},
+		(0, 6) => {
+			let cycles = if y == 6 { 3 } else { 2 };
+			i(opcode, "ld", vec![register(y), Immediate8(n()?)], 2, cycles)
+		},
+		(0, _) => i(opcode, ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"][y as usize], vec![], 1, 1),
+
+		(1, _) => match opcode {
+			0x76 => i(opcode, "halt", vec![], 1, 1),
+			_ => {
+				let cycles = if y == 6 || z == 6 { 2 } else { 1 };
+				i(opcode, "ld", vec![register(y), register(z)], 1, cycles)
+			},
+		},
+
+		(2, _) => {
+			let cycles = if z == 6 { 2 } else { 1 };
+			alu(opcode, y, register(z), 1, cycles)
+		},
+
+		(_, 0) => match y {
+			0 ... 3 => i(opcode, "ret", vec![Condition(CONDITIONS[y as usize])], 1, 2)
+				.taken(5).flow(Flow::ConditionalReturn, None),
+			4 => i(opcode, "ldh", vec![HighAddress(n()?), Register("a")], 2, 3),
+			5 => i(opcode, "add", vec![Register("sp"), Offset(n()? as i8)], 2, 4),
+			6 => i(opcode, "ldh", vec![Register("a"), HighAddress(n()?)], 2, 3),
+			_ => i(opcode, "ld", vec![Register("hl"), StackOffset(n()? as i8)], 2, 3),
+		},
+		(_, 1) => match (q, p) {
+			(0, _) => i(opcode, "pop", vec![Register(STACK_PAIRS[p])], 1, 3),
+			(_, 0) => i(opcode, "ret", vec![], 1, 4).flow(Flow::Return, None),
+			(_, 1) => i(opcode, "reti", vec![], 1, 4).flow(Flow::Return, None),
+			(_, 2) => i(opcode, "jp", vec![Register("hl")], 1, 1).flow(Flow::Return, None),
+			_ => i(opcode, "ld", vec![Register("sp"), Register("hl")], 1, 2),
+		},
+		(_, 2) => match y {
+			0 ... 3 => {
+				let target = nn()?;
+				i(opcode, "jp", vec![Condition(CONDITIONS[y as usize]), Target(target)], 3, 3)
+					.taken(4).flow(Flow::Branch, Some(target))
+			},
+			4 => i(opcode, "ldh", vec![Indirect("c"), Register("a")], 1, 2),
+			5 => i(opcode, "ld", vec![Address(nn()?), Register("a")], 3, 4),
+			6 => i(opcode, "ldh", vec![Register("a"), Indirect("c")], 1, 2),
+			_ => i(opcode, "ld", vec![Register("a"), Address(nn()?)], 3, 4),
+		},
+		(_, 3) => match y {
+			0 => {
+				let target = nn()?;
+				i(opcode, "jp", vec![Target(target)], 3, 4).flow(Flow::Jump, Some(target))
+			},
+			1 => return decode_prefixed(*bytes.get(1)?),
+			6 => i(opcode, "di", vec![], 1, 1),
+			7 => i(opcode, "ei", vec![], 1, 1),
+			_ => invalid(opcode),
+		},
+		(_, 4) => match y {
+			0 ... 3 => {
+				let target = nn()?;
+				i(opcode, "call", vec![Condition(CONDITIONS[y as usize]), Target(target)], 3, 3)
+					.taken(6).flow(Flow::Call, Some(target))
+			},
+			_ => invalid(opcode),
+		},
+		(_, 5) => match (q, p) {
+			(0, _) => i(opcode, "push", vec![Register(STACK_PAIRS[p])], 1, 4),
+			(_, 0) => {
+				let target = nn()?;
+				i(opcode, "call", vec![Target(target)], 3, 6).flow(Flow::Call, Some(target))
+			},
+			_ => invalid(opcode),
+		},
+		(_, 6) => alu(opcode, y, Immediate8(n()?), 2, 2),
+		(_, _) => {
+			let target = (y as u16) * 8;
+			i(opcode, "rst", vec![Target(target)], 1, 4).flow(Flow::Call, Some(target))
+		},
+	};
+	Some(instruction)
+}
+
+// add, adc and sbc name A explicitly, the others imply it
+fn alu(opcode: u8, operation: u8, operand: Operand, length: u16, cycles: u8) -> Instruction {
+	let operands = match operation {
+		0 | 1 | 3 => vec![Operand::Register("a"), operand],
+		_ => vec![operand],
+	};
+	Instruction::new(opcode, ALU[operation as usize], operands, length, cycles)
+}
+
+fn invalid(opcode: u8) -> Instruction {
+	Instruction::new(opcode, "db", vec![Operand::Immediate8(opcode)], 1, 1).flow(Flow::Invalid, None)
+}
+
+// Instructions following the 0xCB prefix
+fn decode_prefixed(opcode: u8) -> Option<Instruction> {
+	let x = opcode >> 6;
+	let y = (opcode >> 3) & 7;
+	let z = opcode & 7;
+	let memory = z == 6;
+
+	let instruction = match x {
+		0 => Instruction::new(0xCB, ROTATIONS[y as usize], vec![register(z)], 2, if memory { 4 } else { 2 }),
+		1 => Instruction::new(0xCB, "bit", vec![Operand::Bit(y), register(z)], 2, if memory { 3 } else { 2 }),
+		_ => Instruction::new(0xCB, ["res", "set"][(x - 2) as usize], vec![Operand::Bit(y), register(z)], 2, if memory { 4 } else { 2 }),
+	};
+	Some(instruction)
+}
@@ -0,0 +1,27 @@
+pub mod decoder;
+pub mod rom;
+
+use core::register::*;
+use core::interconnect::*;
+use self::decoder::*;
+
+// Disassembles the instruction being executed. PC points past the opcode, as it does while executing.
+pub fn disassemble(reg: &Registers, mem: &Interconnect, opcode: u8) -> String {
+	let address = reg.pc.wrapping_sub(1);
+	let bytes = [opcode, mem.read(reg.pc), mem.read(reg.pc.wrapping_add(1))];
+	match decode(&bytes, address) {
+		Some(instruction) => instruction.to_string(),
+		None => format!("db ${:02X}", opcode),
+	}
+}
+
+// Decodes the instruction at address in the memory map
+pub fn decode_at(mem: &Interconnect, address: u16) -> Instruction {
+	let bytes = [
+		mem.read(address),
+		mem.read(address.wrapping_add(1)),
+		mem.read(address.wrapping_add(2)),
+	];
+	// Three bytes always cover the longest instruction
+	decode(&bytes, address).unwrap()
+}
@@ -0,0 +1,257 @@
+use std::io;
+use std::io::Write;
+use std::collections::HashMap;
+use core::disassembler::decoder::*;
+
+// Disassembles a whole ROM image by following the flow of execution from the
+// entry point and the RST/interrupt vectors. Whatever can't be reached is output as data.
+// The listing is RGBDS syntax with one section per bank.
+
+const BANK_SIZE: usize = 0x4000;
+const HEADER_START: usize = 0x0104;
+const HEADER_END: usize = 0x014F;
+// Writes to 0x2000-0x3FFF select the ROM bank on most controllers
+const BANK_SELECT_START: u16 = 0x2000;
+const BANK_SELECT_END: u16 = 0x3FFF;
+
+const FIXED_LABELS: [(usize, &'static str); 28] = [
+	(0x0000, "RST_00"),
+	(0x0008, "RST_08"),
+	(0x0010, "RST_10"),
+	(0x0018, "RST_18"),
+	(0x0020, "RST_20"),
+	(0x0028, "RST_28"),
+	(0x0030, "RST_30"),
+	(0x0038, "RST_38"),
+	(0x0040, "VBlankInterrupt"),
+	(0x0048, "LCDCInterrupt"),
+	(0x0050, "TimerInterrupt"),
+	(0x0058, "SerialInterrupt"),
+	(0x0060, "JoypadInterrupt"),
+	(0x0100, "EntryPoint"),
+	(0x0104, "HeaderLogo"),
+	(0x0134, "HeaderTitle"),
+	(0x013F, "HeaderManufacturer"),
+	(0x0143, "HeaderCGBFlag"),
+	(0x0144, "HeaderNewLicensee"),
+	(0x0146, "HeaderSGBFlag"),
+	(0x0147, "HeaderCartridgeType"),
+	(0x0148, "HeaderROMSize"),
+	(0x0149, "HeaderRAMSize"),
+	(0x014A, "HeaderDestination"),
+	(0x014B, "HeaderOldLicensee"),
+	(0x014C, "HeaderVersion"),
+	(0x014D, "HeaderChecksum"),
+	(0x014E, "HeaderGlobalChecksum"),
+];
+
+// Execution paths start at the entry point and every vector
+const ENTRY_POINTS: [u16; 14] = [
+	0x0100,
+	0x0000, 0x0008, 0x0010, 0x0018, 0x0020, 0x0028, 0x0030, 0x0038,
+	0x0040, 0x0048, 0x0050, 0x0058, 0x0060,
+];
+
+struct Path {
+	bank: usize,
+	address: u16,
+	// Bank we believe is mapped at 0x4000-0x7FFF while running from bank 0
+	selected_bank: usize,
+}
+
+pub struct RomDisassembler<'a> {
+	rom: &'a [u8],
+	banks: usize,
+	// Decoded instructions by ROM offset
+	instructions: HashMap<usize, Instruction>,
+	// ROM offset of the target of each instruction, by the instruction's offset
+	targets: HashMap<usize, usize>,
+	// Bytes that are part of an instruction
+	code: Vec<bool>,
+	labels: HashMap<usize, String>,
+}
+
+impl<'a> RomDisassembler<'a> {
+	pub fn new(rom: &'a [u8]) -> RomDisassembler<'a> {
+		let mut labels = HashMap::new();
+		for &(offset, name) in FIXED_LABELS.iter() {
+			if offset < rom.len() {
+				labels.insert(offset, name.to_owned());
+			}
+		}
+		RomDisassembler {
+			rom: rom,
+			banks: (rom.len() + BANK_SIZE - 1) / BANK_SIZE,
+			instructions: HashMap::new(),
+			targets: HashMap::new(),
+			code: vec![false; rom.len()],
+			labels: labels,
+		}
+	}
+
+	// Where address is found in the ROM image when bank is mapped to 0x4000-0x7FFF
+	fn offset(&self, bank: usize, address: u16) -> Option<usize> {
+		let offset = match address {
+			0x0000 ... 0x3FFF => address as usize,
+			0x4000 ... 0x7FFF if bank > 0 => bank * BANK_SIZE + (address as usize - BANK_SIZE),
+			_ => return None,
+		};
+		match offset < self.rom.len() {
+			true => Some(offset),
+			false => None,
+		}
+	}
+
+	pub fn trace(&mut self) {
+		let mut paths: Vec<Path> = ENTRY_POINTS.iter()
+			.map(|&address| Path { bank: 1, address: address, selected_bank: 1 })
+			.collect();
+		while let Some(path) = paths.pop() {
+			self.trace_path(path, &mut paths);
+		}
+	}
+
+	fn trace_path(&mut self, path: Path, paths: &mut Vec<Path>) {
+		let Path { bank, mut address, mut selected_bank } = path;
+		// Last value loaded into A, to follow bank switches like ld a, 3 / ld [$2000], a
+		let mut a: Option<u8> = None;
+
+		loop {
+			let offset = match self.offset(bank, address) {
+				Some(offset) => offset,
+				None => return,
+			};
+			if offset >= HEADER_START && offset <= HEADER_END || self.code[offset] {
+				return;
+			}
+
+			// Instructions can't run over the end of a bank
+			let bank_end = (offset / BANK_SIZE + 1) * BANK_SIZE;
+			let bytes = &self.rom[offset..bank_end.min(self.rom.len())];
+			let instruction = match decode(bytes, address) {
+				Some(instruction) => instruction,
+				None => return,
+			};
+			let length = instruction.length as usize;
+			if (offset..offset + length).any(|o| self.code[o]) {
+				return;
+			}
+
+			a = match (instruction.mnemonic, instruction.operands.get(0), instruction.operands.get(1)) {
+				("ld", Some(&Operand::Register("a")), Some(&Operand::Immediate8(value))) => Some(value),
+				("ld", Some(&Operand::Address(target)), Some(&Operand::Register("a")))
+					if target >= BANK_SELECT_START && target <= BANK_SELECT_END => {
+					if let Some(value) = a {
+						selected_bank = (value as usize % self.banks.max(1)).max(1);
+					}
+					a
+				},
+				(_, Some(&Operand::Register("a")), _) | ("pop", Some(&Operand::Register("af")), _) => None,
+				_ => a,
+			};
+
+			for o in offset..offset + length {
+				self.code[o] = true;
+			}
+
+			let flow = instruction.flow;
+			if let Some(target) = instruction.target {
+				// From bank 0 we can only guess which bank is mapped
+				let target_bank = if address < 0x4000 { selected_bank } else { bank };
+				if let Some(target_offset) = self.offset(target_bank, target) {
+					self.targets.insert(offset, target_offset);
+					if !self.labels.contains_key(&target_offset) {
+						let prefix = match flow { Flow::Call => "Call", _ => "Jump" };
+						let name = format!("{}_{:03X}_{:04X}", prefix, target_offset / BANK_SIZE, target);
+						self.labels.insert(target_offset, name);
+					}
+					paths.push(Path { bank: target_bank, address: target, selected_bank: selected_bank });
+				}
+			}
+			self.instructions.insert(offset, instruction);
+
+			match flow {
+				Flow::Jump | Flow::Return | Flow::Invalid => return,
+				_ => address = address.wrapping_add(length as u16),
+			}
+		}
+	}
+
+	// Labels can only be placed at the start of an instruction or in data
+	fn label_usable(&self, offset: usize) -> bool {
+		self.instructions.contains_key(&offset) || !self.code[offset]
+	}
+
+	pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+		writeln!(output, "; Disassembled by Gameboy Crust")?;
+		for bank in 0..self.banks {
+			writeln!(output, "")?;
+			match bank {
+				0 => writeln!(output, "SECTION \"ROM Bank $000\", ROM0[$0000]")?,
+				_ => writeln!(output, "SECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[${:X}]", bank, bank)?,
+			}
+
+			let start = bank * BANK_SIZE;
+			let end = (start + BANK_SIZE).min(self.rom.len());
+			let mut offset = start;
+			while offset < end {
+				if let Some(label) = self.labels.get(&offset) {
+					if self.label_usable(offset) {
+						writeln!(output, "\n{}:", label)?;
+					}
+				}
+				offset += match self.instructions.get(&offset) {
+					Some(instruction) => self.write_instruction(output, offset, instruction)?,
+					None => self.write_data(output, offset, end)?,
+				};
+			}
+		}
+		Ok(())
+	}
+
+	fn write_instruction<W: Write>(&self, output: &mut W, offset: usize, instruction: &Instruction) -> io::Result<usize> {
+		let length = instruction.length as usize;
+		// RGBDS always assembles stop as $10 $00
+		let text = if instruction.opcode == 0x10 && self.rom[offset + 1] != 0x00 {
+			format!("db $10, ${:02X}", self.rom[offset + 1])
+		} else {
+			let target = self.targets.get(&offset)
+				.filter(|&&target| self.label_usable(target))
+				.and_then(|target| self.labels.get(target));
+			instruction.format_with(|_| target.cloned())
+		};
+		writeln!(output, "    {:<24}; ${:04X}", text, address_of(offset))?;
+		Ok(length)
+	}
+
+	// Writes up to 16 bytes of data, stopping at the next label or instruction
+	fn write_data<W: Write>(&self, output: &mut W, offset: usize, end: usize) -> io::Result<usize> {
+		let mut length = 1;
+		while length < 16 && offset + length < end {
+			let next = offset + length;
+			if self.instructions.contains_key(&next) || self.labels.contains_key(&next) {
+				break;
+			}
+			length += 1;
+		}
+		let bytes: Vec<String> = self.rom[offset..offset + length].iter()
+			.map(|byte| format!("${:02X}", byte))
+			.collect();
+		writeln!(output, "    db {}", bytes.join(", "))?;
+		Ok(length)
+	}
+}
+
+// Address of a ROM offset as the CPU sees it
+fn address_of(offset: usize) -> u16 {
+	match offset < BANK_SIZE {
+		true => offset as u16,
+		false => (BANK_SIZE + offset % BANK_SIZE) as u16,
+	}
+}
+
+pub fn disassemble_rom<W: Write>(rom: &[u8], output: &mut W) -> io::Result<()> {
+	let mut disassembler = RomDisassembler::new(rom);
+	disassembler.trace();
+	disassembler.write(output)
+}
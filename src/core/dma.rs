@@ -1,49 +1,251 @@
-use memory_map::*;
-use interconnect::*;
+use core::memory_map::*;
+use core::interconnect::*;
+use core::state::*;
 
 // Direct Memory Access
 
+// OAM DMA copies a page of 160 bytes into OAM, one byte per machine cycle.
+// The copy starts one machine cycle after the write to the DMA register, and a new write
+// while a transfer is running restarts it from the new page.
+
+pub const OAM_DMA_LENGTH: u16 = 160;
+
+// Memory buses the CPU and DMA can use at the same time
+#[derive(PartialEq)]
+enum Bus {
+    External,
+    Video,
+    // CGB has work RAM on its own bus, on DMG it shares the external bus with the cartridge
+    Wram,
+}
+
+fn bus(address: u16, cgb_mode: bool) -> Bus {
+    match address {
+        VRAM_START ... VRAM_END => Bus::Video,
+        WRAM_START ... 0xFFFF if cgb_mode => Bus::Wram,
+        _ => Bus::External,
+    }
+}
+
 pub struct OamDma {
-    pub active: bool,
+    // Last value written to the DMA register
+    register: u8,
+    active: bool,
     source: u16,
-    destination: u16,
-    cycles: i32,
+    // Next byte of the page to copy
+    offset: u16,
+    // Page of a requested transfer and the machine cycles until it starts
+    pending: Option<(u16, u8)>,
+    // Byte currently on the bus the transfer reads from
+    value: u8,
 }
 
 impl OamDma {
     pub fn new() -> OamDma {
         OamDma {
+            register: 0xFF,
             active: false,
             source: 0x0000,
-            destination: 0x0000,
-            // 2 cycles to transfer 16 bytes
-            // 1 cycle per 8 bytes (40 * 4bytes per obj) = 160 bytes / 8 bytes per cycle = 20
-            cycles: 20,
+            offset: 0,
+            pending: None,
+            value: 0xFF,
         }
     }
 
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
     pub fn request(&mut self, source: u8) {
-        self.active = true;
-        self.source = (source as u16) * 0x100;
-        self.destination = OAM_START;
+        self.register = source;
+        self.pending = Some(((source as u16) << 8, 1));
     }
 
-    // Returns how many bytes to transfer based on number of cycles passed
-    pub fn cycles(&mut self, cycles: usize) -> (u16, u16, u8) {
-        let from = self.source;
-        let to = self.destination;
-        let mut adjusted_cycles = cycles as i32;
-        if (self.cycles - adjusted_cycles < 0) {
-            adjusted_cycles = self.cycles;
-        }
-        let bytes = (adjusted_cycles * 8) as u8;
-        self.source = self.source.wrapping_add(bytes as u16);
-        self.destination = self.destination.wrapping_add(bytes as u16);
-        self.cycles -= adjusted_cycles;
-        if self.cycles == 0 {
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    // Advances the transfer by one machine cycle. Returns the address to copy from
+    // and the offset in OAM to copy to, if a byte is copied in this cycle.
+    pub fn step(&mut self) -> Option<(u16, u16)> {
+        // A requested transfer starts after a machine cycle of setup, during which
+        // a running transfer keeps going
+        if let Some((source, delay)) = self.pending {
+            if delay > 0 {
+                self.pending = Some((source, delay - 1));
+            } else {
+                self.pending = None;
+                self.active = true;
+                self.source = source;
+                self.offset = 0;
+            }
+        }
+
+        if !self.active {
+            return None;
+        }
+        let offset = self.offset;
+        self.offset += 1;
+        if self.offset >= OAM_DMA_LENGTH {
             self.active = false;
-            self.cycles = 20;
         }
-        (from, to, bytes)
+        Some((self.source + offset, offset))
+    }
+
+    // Records the byte the transfer read, the CPU sees it when reading from the same bus
+    pub fn latch(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    // While a transfer runs the CPU can only use HRAM and the IO registers freely.
+    // OAM reads 0xFF and the bus being copied from returns the byte being copied,
+    // in both cases writes are lost.
+    pub fn conflict(&self, address: u16, cgb_mode: bool) -> Option<u8> {
+        if !self.active {
+            return None;
+        }
+        match address {
+            OAM_START ... UNUSABLE_END => Some(0xFF),
+            IO_START ... 0xFFFF => None,
+            _ if bus(address, cgb_mode) == bus(self.source, cgb_mode) => Some(self.value),
+            _ => None,
+        }
+    }
+}
+
+// CGB VRAM DMA, copies blocks of 16 bytes into VRAM.
+// General purpose DMA (GDMA) copies everything at once, while H-Blank DMA (HDMA)
+// copies a single block every time the GPU enters H-Blank on a visible line.
+
+pub const VRAM_DMA_BLOCK_SIZE: u16 = 16;
+// Each block halts the CPU for 8 M-cycles at normal speed
+pub const VRAM_DMA_BLOCK_CYCLES: usize = 32;
+
+pub struct VramDma {
+    pub hblank_active: bool,
+    source: u16,
+    destination: u16,
+    // Number of 16 byte blocks left to copy
+    blocks: u8,
+}
+
+impl VramDma {
+    pub fn new() -> VramDma {
+        VramDma {
+            hblank_active: false,
+            source: 0x0000,
+            destination: 0x0000,
+            blocks: 0,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            // Bit 7 is clear while an H-Blank transfer is running, set once it's done or stopped
+            HDMA5 => {
+                let remaining = self.blocks.wrapping_sub(1) & 0x7F;
+                match self.hblank_active {
+                    true => remaining,
+                    false => 0x80 | remaining,
+                }
+            },
+            _ => 0xFF,
+        }
+    }
+
+    // Writing HDMA5 starts a transfer and returns the number of blocks
+    // that have to be copied right away for general purpose DMA.
+    pub fn write(&mut self, address: u16, data: u8) -> u8 {
+        match address {
+            HDMA1 => self.source = (self.source & 0x00FF) | ((data as u16) << 8),
+            HDMA2 => self.source = (self.source & 0xFF00) | (data & 0xF0) as u16,
+            HDMA3 => self.destination = (self.destination & 0x00FF) | (((data & 0x1F) as u16) << 8),
+            HDMA4 => self.destination = (self.destination & 0xFF00) | (data & 0xF0) as u16,
+            HDMA5 => {
+                // Clearing bit 7 during an H-Blank transfer stops it
+                if self.hblank_active && (data & 0x80) == 0 {
+                    self.hblank_active = false;
+                    return 0;
+                }
+                self.blocks = (data & 0x7F) + 1;
+                match data & 0x80 {
+                    0 => return self.blocks,
+                    _ => self.hblank_active = true,
+                }
+            },
+            _ => unreachable!(),
+        }
+        0
+    }
+
+    // Returns the source and destination of the next block and advances to the following one
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let from = self.source;
+        let to = VRAM_START | (self.destination & 0x1FF0);
+        self.source = self.source.wrapping_add(VRAM_DMA_BLOCK_SIZE);
+        self.destination = self.destination.wrapping_add(VRAM_DMA_BLOCK_SIZE) & 0x1FF0;
+        self.blocks = self.blocks.saturating_sub(1);
+        if self.blocks == 0 {
+            self.hblank_active = false;
+        }
+        (from, to)
+    }
+}
+
+impl Stateful for VramDma {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.hblank_active);
+        state.write_u16(self.source);
+        state.write_u16(self.destination);
+        state.write_u8(self.blocks);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.hblank_active = state.read_bool()?;
+        self.source = state.read_u16()?;
+        self.destination = state.read_u16()?;
+        self.blocks = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Stateful for OamDma {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        state.write_bool(self.active);
+        state.write_u16(self.source);
+        state.write_u16(self.offset);
+        match self.pending {
+            Some((source, delay)) => {
+                state.write_bool(true);
+                state.write_u16(source);
+                state.write_u8(delay);
+            },
+            None => state.write_bool(false),
+        }
+        state.write_u8(self.value);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.register = state.read_u8()?;
+        self.active = state.read_bool()?;
+        self.source = state.read_u16()?;
+        self.offset = state.read_u16()?;
+        // A running transfer always has bytes left and copies from the start of a page
+        let limit = match self.active { true => OAM_DMA_LENGTH - 1, false => OAM_DMA_LENGTH };
+        if self.offset > limit || (self.source & 0xFF) != 0 {
+            return Err(StateError::InvalidValue);
+        }
+        self.pending = match state.read_bool()? {
+            true => Some((state.read_u16()?, state.read_u8()?)),
+            false => None,
+        };
+        if let Some((source, _)) = self.pending {
+            if (source & 0xFF) != 0 {
+                return Err(StateError::InvalidValue);
+            }
+        }
+        self.value = state.read_u8()?;
+        Ok(())
     }
 }
@@ -0,0 +1,37 @@
+use std::io;
+use std::fmt;
+
+// Anything that stops a ROM from being loaded or run
+#[derive(Debug)]
+pub enum EmuError {
+	Io(io::Error),
+	// Too short to hold a cartridge header
+	RomTooSmall(usize),
+	UnsupportedCartridge(u8),
+	InvalidBootRom(usize),
+	// Line of a palette file that couldn't be read
+	InvalidPalette(usize),
+	// An illegal opcode was executed. Real hardware locks up the CPU until it is switched off,
+	// this is reported once and the rest of the machine keeps running, see CPU::locked.
+	CpuLocked { opcode: u8, address: u16 },
+}
+
+impl fmt::Display for EmuError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			EmuError::Io(ref e) => write!(f, "{}", e),
+			EmuError::RomTooSmall(size) => write!(f, "ROM is too small to be a cartridge: ${:X} bytes", size),
+			EmuError::UnsupportedCartridge(cart_type) => write!(f, "Unsupported Cartridge Type: ${:02X}", cart_type),
+			EmuError::InvalidBootRom(size) => write!(f, "Invalid boot ROM size: ${:X} bytes", size),
+			EmuError::InvalidPalette(line) => write!(f, "Invalid palette on line {}", line),
+			EmuError::CpuLocked { opcode, address } =>
+				write!(f, "CPU locked up by illegal opcode ${:02X} @ ${:04X}", opcode, address),
+		}
+	}
+}
+
+impl From<io::Error> for EmuError {
+	fn from(e: io::Error) -> EmuError {
+		EmuError::Io(e)
+	}
+}
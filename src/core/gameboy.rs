@@ -2,24 +2,206 @@ use core::cpu::*;
 use core::rom::*;
 use core::interconnect::*;
 use core::sink::*;
+use core::joypad::*;
+use core::state::*;
+use core::mbc::rtc::*;
+use core::serial::*;
+use core::boot::*;
+use core::serial::cable::*;
+use core::trace::*;
+use core::error::*;
+use core::gpu::*;
+use core::palette::*;
+
+// One full frame of video, including V-Blank, in cycles
+pub const CYCLES_PER_FRAME: usize = 70224;
 
 pub struct GameBoy {
 	pub interconnect: Interconnect,
-	pub cpu: CPU
+	pub cpu: CPU,
+	tracer: Option<Tracer>,
 }
 
 impl GameBoy {
+	// Picks the hardware the game was made for and skips the boot ROM
 	pub fn new(rom: Rom) -> GameBoy {
+		let model = Model::for_rom(&rom);
+		GameBoy::with_model(rom, model)
+	}
+
+	// Starts right after the boot ROM of the given hardware would have finished
+	pub fn with_model(rom: Rom, model: Model) -> GameBoy {
+		let cgb_game = (rom.cgb_flag() & 0x80) > 0;
+		let mut cpu = CPU::new();
+		cpu.regs = model.post_boot_registers(cgb_game);
+		GameBoy {
+			interconnect: Interconnect::new(rom, model, None),
+			cpu: cpu,
+			tracer: None,
+		}
+	}
+
+	// Starts from power on and runs the boot ROM before the game
+	pub fn with_boot_rom(rom: Rom, boot_rom: BootRom) -> GameBoy {
+		let model = boot_rom.model();
 		GameBoy {
-			interconnect: Interconnect::new(rom),
-			cpu: CPU::new()
+			interconnect: Interconnect::new(rom, model, Some(boot_rom)),
+			cpu: CPU::new(),
+			tracer: None,
+		}
+	}
+
+	// Creates a machine directly from a ROM image in memory
+	pub fn from_bytes(bytes: Vec<u8>) -> Result<GameBoy, EmuError> {
+		Rom::from_bytes(bytes).map(GameBoy::new)
+	}
+
+	// Steps the entire machine through the next instruction and returns cycles taken.
+	// Cycles are counted at normal speed, so double speed instructions take half as long.
+	// The step that locks up the CPU returns the error, after that only the rest of the machine runs.
+	pub fn step(&mut self, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) -> Result<usize, EmuError> {
+		if let Some(ref mut tracer) = self.tracer {
+			tracer.trace(&self.cpu, &self.interconnect);
+		}
+		// The CPU runs the rest of the machine as it accesses memory
+		let mut cycles = self.cpu.step(&mut self.interconnect)? * 4;
+
+		// The CPU is halted while VRAM DMA copies data, the rest of the machine keeps running
+		let stall = self.interconnect.take_dma_stall();
+		if stall > 0 {
+			self.interconnect.run_cycles(stall);
+			cycles += stall;
 		}
+		self.interconnect.flush_sinks(video_sink, audio_sink);
+		let cycles = match self.interconnect.double_speed() {
+			true => cycles / 2,
+			false => cycles,
+		};
+		if let Some(ref mut tracer) = self.tracer {
+			tracer.add_cycles(cycles);
+		}
+		Ok(cycles)
+	}
+
+	// Runs the machine until the next frame has been sent to the sink and returns cycles taken.
+	// If the LCD is switched off no frame is produced, so we stop after one frame's worth of cycles.
+	pub fn run_frame(&mut self, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) -> Result<usize, EmuError> {
+		let mut cycles = 0;
+		while !video_sink.has_frame() && cycles < CYCLES_PER_FRAME {
+			cycles += self.step(video_sink, audio_sink)?;
+		}
+		Ok(cycles)
+	}
+
+	// The last frame drawn by the GPU as 0x00RRGGBB pixels, 160x144
+	pub fn frame_buffer(&self) -> &Vec<u32> {
+		self.interconnect.gpu.frame_buffer()
+	}
+
+	pub fn set_button(&mut self, button: Button, is_pressed: bool) {
+		let interconnect = &mut self.interconnect;
+		interconnect.joypad.set_pressed(&mut interconnect.interrupt, button, is_pressed);
+	}
+
+	// Rumble cartridges report every change of their motor state through this callback
+	pub fn set_rumble_callback<F: FnMut(bool) + 'static>(&mut self, callback: F) {
+		self.interconnect.rom_mut().set_rumble_callback(Box::new(callback));
+	}
+
+	// Picks the scanline renderer (the default) or the slower, more accurate pixel FIFO
+	pub fn set_renderer(&mut self, renderer: Renderer) {
+		self.interconnect.gpu.set_renderer(renderer);
+	}
+
+	// Colors DMG games are drawn in, see palette::PRESETS
+	pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+		self.interconnect.gpu.set_dmg_palette(palette);
+	}
+
+	// Draws CGB colors like the CGB LCD shows them instead of at full saturation
+	pub fn set_color_correction(&mut self, enabled: bool) {
+		self.interconnect.gpu.set_color_correction(enabled);
+	}
+
+	// Logs every instruction executed from now on, see Tracer
+	pub fn set_tracer(&mut self, tracer: Tracer) {
+		self.tracer = Some(tracer);
+	}
+
+	pub fn take_tracer(&mut self) -> Option<Tracer> {
+		self.tracer.take()
+	}
+
+	// Plugs a link cable into the serial port
+	pub fn set_link_port<P: LinkPort + 'static>(&mut self, port: P) {
+		self.interconnect.serial.set_link_port(Box::new(port));
+	}
+
+	// Connects two machines in the same process with a link cable
+	pub fn connect_link_cable(&mut self, other: &mut GameBoy) {
+		let (a, b) = link_cable();
+		self.set_link_port(a);
+		other.set_link_port(b);
+	}
+
+	// Replaces the wall clock used by cartridges with a real time clock, e.g. to fake time
+	pub fn set_rtc_clock<C: RtcClock + 'static>(&mut self, clock: C) {
+		self.interconnect.rom_mut().set_rtc_clock(Box::new(clock));
+	}
+
+	// Snapshot of the entire machine
+	pub fn save_state(&self) -> Vec<u8> {
+		let mut state = StateWriter::new();
+		for &byte in STATE_MAGIC.iter() {
+			state.write_u8(byte);
+		}
+		state.write_u8(STATE_VERSION);
+		self.write_rom_header(&mut state);
+		self.cpu.save_state(&mut state);
+		self.interconnect.save_state(&mut state);
+		state.consume()
+	}
+
+	// Restores a snapshot made by save_state. The machine is left untouched if it fails.
+	pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+		let mut state = StateReader::new(data);
+		for &byte in STATE_MAGIC.iter() {
+			if state.read_u8()? != byte {
+				return Err(StateError::InvalidHeader);
+			}
+		}
+		let version = state.read_u8()?;
+		if version != STATE_VERSION {
+			return Err(StateError::UnsupportedVersion(version));
+		}
+
+		// The snapshot must have been taken with the same game
+		let mut expected = StateWriter::new();
+		self.write_rom_header(&mut expected);
+		for &byte in expected.consume().iter() {
+			if state.read_u8()? != byte {
+				return Err(StateError::RomMismatch);
+			}
+		}
+
+		let backup = self.save_state();
+		let result = self.load_machine(&mut state);
+		if result.is_err() {
+			self.load_state(&backup).expect("Unable to restore machine state");
+		}
+		result
+	}
+
+	fn load_machine(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.cpu.load_state(state)?;
+		self.interconnect.load_state(state)
 	}
 
-	// Steps the entire machine through the next instruction and returns cycles taken
-	pub fn step(&mut self, video_sink: &mut VideoSink) -> usize {
-		let cycles = self.cpu.step(&mut self.interconnect) * 4;
-		self.interconnect.cycles(cycles, video_sink);
-		cycles
+	// Title and checksums identify the ROM the snapshot belongs to
+	fn write_rom_header(&self, state: &mut StateWriter) {
+		let rom = self.interconnect.rom();
+		state.write_bytes(rom.name().as_bytes());
+		state.write_u8(rom.header_checksum());
+		state.write_u16(rom.global_checksum());
 	}
 }
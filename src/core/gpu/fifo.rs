@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use core::helper::*;
+use core::sink::*;
+use core::interrupt::*;
+use super::*;
+
+// Pixel FIFO renderer, the accurate alternative to drawing a whole scanline at once.
+// Every dot of mode 3 runs the background fetcher and shifts at most one pixel out to the LCD,
+// so register writes in the middle of a line take effect where they happen and mode 3 gets
+// longer for fine scrolling, the window and sprites like it does on hardware.
+
+// The first tile fetched on every line is thrown away
+const STARTUP_DOTS: u8 = 7;
+// Tile number, low byte and high byte take two dots each
+const TILE_FETCH_DOTS: u8 = 6;
+const SPRITE_FETCH_DOTS: usize = 6;
+
+#[derive(Clone, Copy)]
+struct BgPixel {
+	color: u8,
+	attributes: u8,
+}
+
+#[derive(Clone, Copy)]
+struct ObjPixel {
+	color: u8,
+	// Index of the sprite in OAM
+	sprite: usize,
+}
+
+const TRANSPARENT: ObjPixel = ObjPixel { color: 0, sprite: 0 };
+
+pub struct Fifo {
+	bg: VecDeque<BgPixel>,
+	obj: VecDeque<ObjPixel>,
+	startup: u8,
+	// Dots spent on the tile being fetched
+	fetch_dots: u8,
+	// Tile column of the next fetch, counted from the start of the line or the window
+	fetch_x: u8,
+	// Next LCD column to be drawn
+	x: u8,
+	// Pixels still to be thrown away for fine scrolling
+	discard: u8,
+	// The fetcher has switched to the window on this line
+	window: bool,
+	// Bits of the sprites found by the OAM scan that have been fetched already
+	fetched: u16,
+	// Dots left until the sprite being fetched is mixed in
+	sprite_dots: usize,
+	sprite_pending: Option<usize>,
+	// Background tile the last sprite fetch waited for, the fetcher is only waited on once per tile
+	penalty_tile: Option<u8>,
+}
+
+impl Fifo {
+	pub fn new() -> Fifo {
+		Fifo {
+			bg: VecDeque::with_capacity(16),
+			obj: VecDeque::with_capacity(8),
+			startup: STARTUP_DOTS,
+			fetch_dots: 0,
+			fetch_x: 0,
+			x: 0,
+			discard: 0,
+			window: false,
+			fetched: 0,
+			sprite_dots: 0,
+			sprite_pending: None,
+			penalty_tile: None,
+		}
+	}
+}
+
+impl Gpu {
+	pub(super) fn fifo_cycles(&mut self, cycles: usize, interrupt: &mut InterruptHandler, video_sink: &mut VideoSink) {
+		for _ in 0..cycles {
+			self.fifo_dot(interrupt, video_sink);
+		}
+	}
+
+	fn fifo_dot(&mut self, interrupt: &mut InterruptHandler, video_sink: &mut VideoSink) {
+		// LY wraps early on the last line, so the line is counted from the start of the frame
+		let line = self.frame_cycles / HBLANK_PERIOD;
+
+		if line < FRAME_HEIGHT {
+			match self.scanline_cycles {
+				0 => {
+					if line == 0 {
+						self.reset_window();
+					}
+					self.check_window_y();
+					// The first line after the LCD is turned on doesn't report mode 2
+					if !self.first_line {
+						self.set_mode(StatusMode::Oam);
+					}
+					self.scan_oam();
+				},
+				OAM_PERIOD => {
+					self.set_mode(StatusMode::Transfer);
+					self.first_line = false;
+					self.start_transfer();
+				},
+				_ => {},
+			}
+
+			if self.get_mode() == StatusMode::Transfer {
+				self.transfer_dot();
+				if self.fifo.x as usize == FRAME_WIDTH {
+					self.set_mode(StatusMode::HBlank);
+					self.hblank_started = true;
+					if self.fifo.window {
+						self.window_line = self.window_line.wrapping_add(1);
+					}
+				}
+			}
+		} else if line == FRAME_HEIGHT && self.scanline_cycles == 0 {
+			self.set_mode(StatusMode::VBlank);
+			interrupt.request_interrupt(InterruptFlag::VBlank);
+			video_sink.append(self.frame_buffer.clone());
+		}
+
+		self.update_stat_line(interrupt);
+
+		self.scanline_cycles += 1;
+		self.frame_cycles += 1;
+		if self.scanline_cycles == HBLANK_PERIOD {
+			self.scanline_cycles = 0;
+			if line == LINES_PER_FRAME - 1 {
+				self.LY.clear();
+				self.frame_cycles = 0;
+			} else {
+				self.LY.add(1);
+			}
+		}
+		self.wrap_last_line();
+	}
+
+	pub(super) fn start_transfer(&mut self) {
+		let fifo = &mut self.fifo;
+		fifo.bg.clear();
+		fifo.obj.clear();
+		fifo.startup = STARTUP_DOTS;
+		fifo.fetch_dots = 0;
+		fifo.fetch_x = 0;
+		fifo.x = 0;
+		fifo.discard = self.SCX.get() & 0x07;
+		fifo.window = false;
+		fifo.fetched = 0;
+		fifo.sprite_dots = 0;
+		fifo.sprite_pending = None;
+		fifo.penalty_tile = None;
+	}
+
+	fn transfer_dot(&mut self) {
+		if self.fifo.startup > 0 {
+			self.fifo.startup -= 1;
+			return;
+		}
+
+		// Fetching a sprite stalls both the background fetcher and the LCD
+		if self.fifo.sprite_dots > 0 {
+			self.fifo.sprite_dots -= 1;
+			if self.fifo.sprite_dots == 0 {
+				if let Some(index) = self.fifo.sprite_pending.take() {
+					self.load_sprite(index);
+				}
+			}
+			return;
+		}
+
+		if !self.fifo.window && self.window_starts() {
+			let fifo = &mut self.fifo;
+			fifo.window = true;
+			fifo.bg.clear();
+			fifo.fetch_dots = 0;
+			fifo.fetch_x = 0;
+			// With WX below 7 the window starts scrolled off the left edge
+			let wx = self.WX.get();
+			if wx < 7 && fifo.x == 0 {
+				fifo.discard = 7 - wx;
+			}
+		}
+
+		if self.start_sprite_fetch() {
+			return;
+		}
+
+		self.fetch_dot();
+
+		if self.fifo.bg.is_empty() {
+			return;
+		}
+		let pixel = self.fifo.bg.pop_front().unwrap();
+		if self.fifo.discard > 0 {
+			self.fifo.discard -= 1;
+			return;
+		}
+		let sprite = self.fifo.obj.pop_front();
+		self.output_pixel(pixel, sprite);
+		self.fifo.x += 1;
+	}
+
+	fn window_starts(&self) -> bool {
+		if !self.LCDC.is_set(Bit::Bit5) || !self.window_y_triggered {
+			return false;
+		}
+		// Only the fine scroll of the background is thrown away, never window pixels
+		let wx = self.WX.get() as usize;
+		match wx < 7 {
+			true => self.fifo.x == 0 && self.fifo.discard == 0,
+			false => self.fifo.x as usize + 7 == wx,
+		}
+	}
+
+	// Starts fetching the next sprite that begins at the current column, returns true if it did
+	fn start_sprite_fetch(&mut self) -> bool {
+		if !self.LCDC.is_set(Bit::Bit1) || self.fifo.discard > 0 {
+			return false;
+		}
+		let x = self.fifo.x as i32;
+		// Sprites with X = 0 in OAM are hidden, but still count towards the limit.
+		// Sprites at the same X are fetched in OAM order.
+		let position = (0..self.line_sprite_count).find(|&i| {
+			let sprite_x = self.sprite_table[self.line_sprites[i]].x_pos;
+			(self.fifo.fetched & (1 << i)) == 0 && sprite_x > -8 && sprite_x <= x
+		});
+		let index = match position {
+			Some(position) => {
+				self.fifo.fetched |= 1 << position;
+				self.line_sprites[position]
+			},
+			None => return false,
+		};
+
+		// The background fetcher has to finish the tile it is working on first
+		let tile_x = match self.fifo.window {
+			true => (x as u8).wrapping_add(7).wrapping_sub(self.WX.get()),
+			false => (x as u8).wrapping_add(self.SCX.get()),
+		};
+		let tile = tile_x / 8;
+		let wait = match self.fifo.penalty_tile == Some(tile) {
+			true => 0,
+			false => 5 - (tile_x % 8).min(5) as usize,
+		};
+		self.fifo.penalty_tile = Some(tile);
+		// This dot is the first of the fetch
+		self.fifo.sprite_dots = SPRITE_FETCH_DOTS + wait - 1;
+		self.fifo.sprite_pending = Some(index);
+		true
+	}
+
+	// Runs the background fetcher for a dot, pushing a tile once the FIFO has run empty
+	fn fetch_dot(&mut self) {
+		if self.fifo.fetch_dots < TILE_FETCH_DOTS {
+			self.fifo.fetch_dots += 1;
+			return;
+		}
+		if !self.fifo.bg.is_empty() {
+			return;
+		}
+
+		let fetch_x = self.fifo.fetch_x.wrapping_mul(8);
+		let (map_select, x, y) = match self.fifo.window {
+			true => (Bit::Bit6, fetch_x, self.window_line),
+			false => {
+				let x = (self.SCX.get() & !0x07).wrapping_add(fetch_x);
+				(Bit::Bit3, x, self.LY.get().wrapping_add(self.SCY.get()))
+			},
+		};
+		let tile_map_location = match self.LCDC.is_set(map_select) {
+			true  => 0x9C00,
+			false => 0x9800,
+		};
+		for i in 0..8 {
+			let (color, attributes) = self.map_pixel(tile_map_location, x.wrapping_add(i), y);
+			self.fifo.bg.push_back(BgPixel { color: color, attributes: attributes });
+		}
+		self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+		self.fifo.fetch_dots = 0;
+	}
+
+	// Mixes a fetched sprite into the sprite FIFO, without covering pixels of earlier sprites
+	fn load_sprite(&mut self, index: usize) {
+		let sprite = self.sprite_table[index].clone();
+		let pixels = self.sprite_pixels(&sprite, self.LY.get());
+		// Part of the sprite may be off the left edge
+		let skip = (self.fifo.x as i32 - sprite.x_pos).max(0) as usize;
+		while self.fifo.obj.len() < 8 {
+			self.fifo.obj.push_back(TRANSPARENT);
+		}
+		for i in skip..8 {
+			let color = pixels[i];
+			let slot = &mut self.fifo.obj[i - skip];
+			// On CGB the sprite first in OAM wins, on DMG the one fetched first
+			let replace = slot.color == 0 || (self.cgb_mode && index < slot.sprite);
+			if color != 0 && replace {
+				*slot = ObjPixel { color: color, sprite: index };
+			}
+		}
+	}
+
+	fn output_pixel(&mut self, pixel: BgPixel, sprite: Option<ObjPixel>) {
+		// On DMG clearing LCDC bit 0 blanks the background and the window
+		let pixel = match self.cgb_mode || self.LCDC.is_set(Bit::Bit0) {
+			true => pixel,
+			false => BgPixel { color: 0, attributes: 0 },
+		};
+		let mut color = match self.cgb_mode {
+			true => self.cgb_colorize(&self.bg_palette_ram, pixel.attributes & 0x07, pixel.color),
+			false => self.colorize(pixel.color, self.BGP.get(), &self.dmg_palette.bg),
+		};
+
+		if let Some(sprite) = sprite {
+			if sprite.color != 0 && self.LCDC.is_set(Bit::Bit1) {
+				let entry = &self.sprite_table[sprite.sprite];
+				let master_priority = !self.cgb_mode || self.LCDC.is_set(Bit::Bit0);
+				let behind = entry.behind_background || (pixel.attributes & Bit::Bit7 as u8) > 0;
+				if !(master_priority && pixel.color != 0 && behind) {
+					color = match self.cgb_mode {
+						true => self.cgb_colorize(&self.obj_palette_ram, entry.cgb_palette, sprite.color),
+						false => {
+							let (palette, colors) = match entry.use_palette_one {
+								false => (self.OBP0.get(), &self.dmg_palette.obj0),
+								true  => (self.OBP1.get(), &self.dmg_palette.obj1),
+							};
+							self.colorize(sprite.color, palette, colors)
+						},
+					};
+				}
+			}
+		}
+
+		let offset = (self.LY.get() as usize * FRAME_WIDTH) + self.fifo.x as usize;
+		self.frame_buffer[offset] = color;
+	}
+}
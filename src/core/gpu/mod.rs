@@ -0,0 +1,963 @@
+use core::helper::*;
+use core::sink::*;
+use core::memory_map::*;
+use core::interrupt::*;
+use core::state::*;
+use core::palette::*;
+use std::cmp;
+use self::fifo::*;
+
+mod fifo;
+
+const FRAME_WIDTH: usize = 160;
+const FRAME_HEIGHT: usize = 144;
+
+const TILE_RAM_END: u16 = 0x97FF;
+
+const VRAM_BANK_SIZE: usize = 8192; // 8Kb Bank
+const VRAM_SIZE: usize = VRAM_BANK_SIZE * 2; // 2 banks in CGB
+const OAM_SIZE: usize = 160; // 160byte OAM memory
+const PALETTE_RAM_SIZE: usize = 64; // 8 palettes, 4 colors each, 2 bytes per color
+
+const TILES_PER_BANK: usize = 384;
+
+// Flags for each background pixel, used to determine sprite priority
+const BG_OPAQUE: u8 = 0b01; // color is not zero
+const BG_PRIORITY: u8 = 0b10; // CGB tile attribute says BG is drawn over sprites
+
+// time in cycles for each mode to complete
+// Read -> Transfer -> Hblank (reapeat...) until Vblank
+const OAM_PERIOD: usize        = 80; // 77-83 cycles, 80 average
+const TRANSFER_PERIOD: usize   = OAM_PERIOD + 172; // 169-175 cycles, 172 average
+const HBLANK_PERIOD: usize     = 456; // 456 cycles
+
+// time in cycles for rendering full screen and vblank
+const FRAME_PERIOD: usize      = HBLANK_PERIOD * FRAME_HEIGHT; // 65,664 cycles for full frame
+const VBLANK_PERIOD: usize     = FRAME_PERIOD + 4560; // 4,560 cycles for vblank
+const LINES_PER_FRAME: usize   = 154; // 144 visible lines and 10 lines of vblank
+const SPRITES_PER_LINE: usize  = 10;
+
+// How the picture is drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Renderer {
+	// Draws a whole line at the start of mode 3, fast but mid-line register writes are missed
+	Scanline,
+	// Draws dot by dot through the pixel FIFO, mode 3 length varies like on hardware
+	Fifo,
+}
+
+// Status of the LCD controller
+#[derive(Debug, PartialEq)]
+enum StatusMode {
+	HBlank   = 0,
+	VBlank   = 1,
+	Oam      = 2,
+	Transfer = 3,
+}
+
+enum StatusInterrupt {
+	HBlank      = 0b00001000,
+	VBlank      = 0b00010000,
+	Oam         = 0b00100000,
+	Coincidence = 0b01000000,
+}
+
+// Entry for the tile cache
+#[derive(Clone, Debug)]
+struct TileEntry {
+	dirty: bool,
+	pixels: Vec<u8>,
+}
+
+impl TileEntry {
+	pub fn new() -> TileEntry {
+		TileEntry {
+			dirty: true,
+			pixels: vec![0; 64],
+		}
+	}
+}
+
+// Entry for the sprite table
+#[derive(Clone, Debug)]
+struct SpriteEntry {
+	y_pos: i32,
+	x_pos: i32,
+	tile_id: u8,
+	behind_background: bool,
+	x_flip: bool,
+	y_flip: bool,
+	use_palette_one: bool,
+	vram_bank: u8, // CGB only
+	cgb_palette: u8, // CGB only
+}
+
+impl SpriteEntry {
+	pub fn new() -> SpriteEntry {
+		// Matches OAM filled with zeroes, which is off screen
+		SpriteEntry {
+			y_pos: -16,
+			x_pos: -8,
+			tile_id: 0,
+			behind_background: false,
+			x_flip: false,
+			y_flip: false,
+			use_palette_one: false,
+			vram_bank: 0,
+			cgb_palette: 0,
+		}
+	}
+}
+
+pub struct Gpu {
+	// Memory
+	Vram: Vec<u8>,
+	Oam: Vec<u8>,
+	// Tile Cache
+	tile_cache: Vec<TileEntry>, // cache rules everything around me
+	// Sprite Table
+	sprite_table: Vec<SpriteEntry>,
+	// OAM indexes of the sprites on the current line, in OAM order
+	line_sprites: [usize; SPRITES_PER_LINE],
+	line_sprite_count: usize,
+	// Frame Buffer
+	frame_buffer: Vec<u32>,
+	// Registers
+	pub LCDC: MemoryRegister,
+	pub STAT: MemoryRegister,
+	pub LYC: MemoryRegister,
+	pub LY: MemoryRegister,
+	pub BGP: MemoryRegister,
+	pub OBP0: MemoryRegister,
+	pub OBP1: MemoryRegister,
+	pub SCY: MemoryRegister,
+	pub SCX: MemoryRegister,
+	pub WY: MemoryRegister,
+	pub WX: MemoryRegister,
+	scanline_cycles: usize,
+	frame_cycles: usize,
+	// Game Boy Color
+	cgb_mode: bool,
+	vram_bank: u8,
+	bg_palette_ram: Vec<u8>,
+	obj_palette_ram: Vec<u8>,
+	pub BCPS: MemoryRegister,
+	pub OCPS: MemoryRegister,
+	// Set when a visible line enters H-Blank, drives H-Blank DMA
+	hblank_started: bool,
+	renderer: Renderer,
+	fifo: Fifo,
+	// Level of the STAT interrupt line, the interrupt is requested on its rising edge
+	stat_line: bool,
+	// The LCD has just been turned on and is drawing its first line
+	first_line: bool,
+	// Row of the window drawn next, only advances on lines the window was drawn on
+	window_line: u8,
+	// LY matched WY at the start of a line this frame, the window can show up from then on
+	window_y_triggered: bool,
+	// Output colors, not part of the machine state
+	dmg_palette: DmgPalette,
+	color_correction: bool,
+}
+
+impl Gpu {
+	pub fn new(cgb_mode: bool) -> Gpu {
+		Gpu {
+			Vram: vec![0; VRAM_SIZE],
+			Oam:  vec![0; OAM_SIZE],
+			tile_cache: vec![TileEntry::new(); TILES_PER_BANK * 2],
+			sprite_table: vec![SpriteEntry::new(); 40],
+			line_sprites: [0; SPRITES_PER_LINE],
+			line_sprite_count: 0,
+			frame_buffer: vec![0xFF00FF; FRAME_WIDTH * FRAME_HEIGHT],
+			LCDC: MemoryRegister::new(0x00),
+			STAT: MemoryRegister::new(0x02),
+			LYC: MemoryRegister::new(0x00),
+			LY: MemoryRegister::new(0x00),
+			BGP: MemoryRegister::new(0x00),
+			OBP0: MemoryRegister::new(0x00),
+			OBP1: MemoryRegister::new(0x00),
+			SCY: MemoryRegister::new(0x00),
+			SCX: MemoryRegister::new(0x00),
+			WY: MemoryRegister::new(0x00),
+			WX: MemoryRegister::new(0x00),
+			scanline_cycles: 0,
+			frame_cycles: 0,
+			cgb_mode: cgb_mode,
+			vram_bank: 0,
+			bg_palette_ram: vec![0xFF; PALETTE_RAM_SIZE],
+			obj_palette_ram: vec![0xFF; PALETTE_RAM_SIZE],
+			BCPS: MemoryRegister::new(0x00),
+			OCPS: MemoryRegister::new(0x00),
+			hblank_started: false,
+			renderer: Renderer::Scanline,
+			fifo: Fifo::new(),
+			stat_line: false,
+			first_line: false,
+			window_line: 0,
+			window_y_triggered: false,
+			dmg_palette: DEFAULT_PALETTE,
+			color_correction: false,
+		}
+	}
+
+	// Converts a 0-3 shade to the appropriate 32bit palette color
+	fn colorize(&self, shade: u8, palette: u8, color_values: &[u32; 4]) -> u32 {
+		let real_shade = match shade {
+			0 =>  palette & 0b00000011,
+			1 => (palette & 0b00001100) >> 2,
+			2 => (palette & 0b00110000) >> 4,
+			3 => (palette & 0b11000000) >> 6,
+			_ => panic!("Invalid Palette Shade!")
+		};
+		color_values[real_shade as usize]
+	}
+
+	// Converts a CGB palette RAM entry (15-bit BGR) to a 32bit color
+	fn cgb_colorize(&self, palette_ram: &Vec<u8>, palette: u8, shade: u8) -> u32 {
+		let index = (palette as usize * 8) + (shade as usize * 2);
+		let color = combine!(palette_ram[index + 1], palette_ram[index]);
+		let red = (color & 0x1F) as u32;
+		let green = ((color >> 5) & 0x1F) as u32;
+		let blue = ((color >> 10) & 0x1F) as u32;
+		if self.color_correction {
+			return correct_color(red, green, blue);
+		}
+		// Scale 5 bits to 8 bits
+		let scale = |c: u32| (c << 3) | (c >> 2);
+		(scale(red) << 16) | (scale(green) << 8) | scale(blue)
+	}
+
+	// The most recently rendered 160x144 frame
+	pub fn frame_buffer(&self) -> &Vec<u32> {
+		&self.frame_buffer
+	}
+
+	// Returns a 128x192px display for entire tile cache for debugging
+	// Tile cache is 384 tiles, entire VRAM is turned into a tile cache
+	// Even though we only use certain areas, it makes it easier to cache
+	// Entire VRAM as if all data were tiles.
+	pub fn get_tiles(&mut self) -> Vec<u32> {
+		let width = 128;
+		let height = 192;
+		let mut display = vec![0xFF00FF; width * height];
+		let palette = self.BGP.get();
+		// Loop entire VRAM as tiles
+		for index in 0..384 {
+
+			if self.tile_cache[index].dirty {
+				self.refresh_tile(index);
+			}
+
+			for y in 0..8 {
+				for x in 0..8 {
+					let raw_pixel = self.tile_cache[index].pixels[(y * 8) + x];
+					let color = self.colorize(raw_pixel, palette, &self.dmg_palette.bg);
+					let column = index % 16;
+					let row = index / 16;
+					let width_offset = (column * 8) + x;
+					let height_offset = ((row * 8) + y) * width;
+					let vec_offset = width_offset + height_offset;
+					display[vec_offset] = color;
+				}
+			}
+		}
+		display
+	}
+
+	// Updates the tile cache with the current data in VRAM for that tile
+	// Tiles 0-383 are in VRAM bank 0, tiles 384-767 in bank 1
+	pub fn refresh_tile(&mut self, id: usize) {
+		let offset = ((id / TILES_PER_BANK) * VRAM_BANK_SIZE) + ((id % TILES_PER_BANK) * 16);
+
+		let mut tile = vec![0; 64];
+
+		for y in 0..8 {
+			let low_byte = self.Vram[offset + (y * 2)];
+			let high_byte = self.Vram[offset + (y * 2) + 1];
+			// Loop through all the pixels in a y value, bit 7 is the leftmost pixel
+			for x in 0..8 {
+				let low_bit = (low_byte >> (7 - x)) & 1;
+				let high_bit = (high_byte >> (7 - x)) & 1;
+				tile[(y * 8) + x] = (high_bit << 1) | low_bit;
+			}
+		}
+
+		self.tile_cache[id].dirty = false;
+		self.tile_cache[id].pixels = tile;
+	}
+
+	pub fn cycles(&mut self, cycles: usize, interrupt: &mut InterruptHandler, video_sink: &mut VideoSink) {
+
+		if !self.display_enabled() {
+			return;
+		}
+
+		if self.renderer == Renderer::Fifo {
+			self.fifo_cycles(cycles, interrupt, video_sink);
+			return;
+		}
+
+		let old_mode = self.get_mode();
+
+		self.scanline_cycles += cycles;
+		self.frame_cycles += cycles;
+
+		// we are in vblank
+		if self.frame_cycles >= FRAME_PERIOD {
+
+			// We have just entered the Vblank period
+			if old_mode != StatusMode::VBlank {
+				self.set_mode(StatusMode::VBlank);
+				// Call the appropriate interrupt
+				interrupt.request_interrupt(InterruptFlag::VBlank);
+				video_sink.append(self.frame_buffer.clone());
+			}
+
+			// we have completed vblank period, reset everything, update sink
+			if self.frame_cycles >= VBLANK_PERIOD {
+				self.frame_cycles -= VBLANK_PERIOD;
+				self.scanline_cycles = self.frame_cycles;
+				self.LY.clear();
+				self.set_mode(StatusMode::Oam);
+				self.reset_window();
+			}
+
+		} else {
+			// Update the scanline state
+			match self.scanline_cycles {
+				0 ... OAM_PERIOD => { // OAM
+					// The first line after the LCD is turned on doesn't report mode 2
+					if old_mode != StatusMode::Oam && !self.first_line {
+						self.set_mode(StatusMode::Oam);
+					}
+				},
+				OAM_PERIOD ... TRANSFER_PERIOD => { // Transfer
+					if old_mode != StatusMode::Transfer {
+						self.set_mode(StatusMode::Transfer);
+						self.first_line = false;
+						// The LCD controller is now transferring data from VRAM to screen.
+						// Udpate the internal framebuffer at the current scanline to mimic this.
+						self.update_scanline();
+					}
+				},
+				TRANSFER_PERIOD ... HBLANK_PERIOD => { // H-Blank 
+					// We have just entered H-Blank
+					if old_mode != StatusMode::HBlank {
+						self.set_mode(StatusMode::HBlank);
+						self.hblank_started = true;
+					}
+				},
+				_ => {},
+			}
+		}
+
+		// If we have finished the H-Blank period, we are on a new line
+		// LY is updated even if we are in V-blank
+		if self.scanline_cycles >= HBLANK_PERIOD {
+			self.LY.add(1);
+			self.scanline_cycles -= HBLANK_PERIOD;
+		}
+		self.wrap_last_line();
+
+		self.update_stat_line(interrupt);
+	}
+
+	// LY only reads 153 for the first machine cycle of the last line, then already reads 0
+	fn wrap_last_line(&mut self) {
+		if self.LY.get() as usize == LINES_PER_FRAME - 1 && self.scanline_cycles >= 4 {
+			self.LY.clear();
+		}
+	}
+
+	// All STAT interrupt sources are ORed into a single line and the interrupt is requested
+	// when it goes high. While one source keeps the line high, other sources becoming
+	// active don't request another interrupt ("STAT blocking").
+	pub fn update_stat_line(&mut self, interrupt: &mut InterruptHandler) {
+		if !self.display_enabled() {
+			self.stat_line = false;
+			return;
+		}
+
+		// LY == LYC Coincidence flag
+		if self.LY.get() == self.LYC.get() {
+			self.STAT.set_bit(Bit::Bit2);
+		} else {
+			self.STAT.clear_bit(Bit::Bit2);
+		}
+
+		let stat = self.STAT.get();
+		let enabled = |source: StatusInterrupt| (stat & source as u8) > 0;
+		let mode = self.get_mode();
+		// The mode 2 source also goes high at the start of V-Blank
+		let vblank_start = mode == StatusMode::VBlank && self.LY.get() as usize == FRAME_HEIGHT && self.scanline_cycles < 4;
+		let line = (enabled(StatusInterrupt::Coincidence) && self.STAT.is_set(Bit::Bit2))
+			|| (enabled(StatusInterrupt::HBlank) && mode == StatusMode::HBlank)
+			|| (enabled(StatusInterrupt::VBlank) && mode == StatusMode::VBlank)
+			|| (enabled(StatusInterrupt::Oam) && (mode == StatusMode::Oam || vblank_start));
+
+		if line && !self.stat_line {
+			interrupt.request_interrupt(InterruptFlag::Lcdc);
+		}
+		self.stat_line = line;
+	}
+
+	pub fn set_renderer(&mut self, renderer: Renderer) {
+		// Both renderers agree on the position in the frame, the current line is drawn by the new one from the next line
+		self.frame_cycles = (self.LY.get() as usize * HBLANK_PERIOD) + self.scanline_cycles;
+		self.renderer = renderer;
+		self.start_transfer();
+	}
+
+	// Colors DMG games are drawn in
+	pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+		self.dmg_palette = palette;
+	}
+
+	// Makes CGB colors look like they do on the CGB LCD
+	pub fn set_color_correction(&mut self, enabled: bool) {
+		self.color_correction = enabled;
+	}
+
+	pub fn renderer(&self) -> Renderer {
+		self.renderer
+	}
+
+	// Returns true once each time a visible line has entered H-Blank
+	pub fn take_hblank(&mut self) -> bool {
+		let started = self.hblank_started;
+		self.hblank_started = false;
+		started
+	}
+
+	// Draw the current scanline on the internal framebuffer
+	fn update_scanline(&mut self) {
+		// A helper vector to determine sprite priority relative to bg
+		// holds BG_OPAQUE/BG_PRIORITY flags for every pixel
+		let mut bg_priority = [0; FRAME_WIDTH];
+		self.check_window_y();
+		// If BG enabled, draw it. In CGB mode the background is always drawn
+		// and LCDC bit 0 controls the background priority over sprites instead.
+		if self.cgb_mode || self.LCDC.is_set(Bit::Bit0) {
+			self.draw_background(&mut bg_priority);
+		}
+
+		if self.LCDC.is_set(Bit::Bit5) {
+			self.draw_window(&mut bg_priority);
+		}
+
+		// If sprites are enabled, draw them
+		if self.LCDC.is_set(Bit::Bit1) {
+			self.draw_sprites(&mut bg_priority);
+		}
+	}
+
+	// Returns the shade of a pixel in a background/window tile map and the tile attributes.
+	// x and y are coordinates within the 256x256 map. Attributes are always 0 outside of CGB mode.
+	fn map_pixel(&mut self, tile_map_location: u16, x: u8, y: u8) -> (u8, u8) {
+		let tile_map_index = ((y / 8) as u16 * 32) + (x / 8) as u16;
+		let lookup = tile_map_location + tile_map_index;
+		let tile_pattern = self.read_raw(lookup);
+
+		// CGB tile attributes are stored in VRAM bank 1 at the same location as the tile number
+		// Bit 7: BG priority, Bit 6: Y flip, Bit 5: X flip, Bit 3: VRAM bank, Bits 0-2: palette
+		let attributes = match self.cgb_mode {
+			true => self.Vram[VRAM_BANK_SIZE + (lookup - VRAM_START) as usize],
+			false => 0,
+		};
+
+		let vram_location = match self.LCDC.is_set(Bit::Bit4) {
+			false => {
+				let adjusted = ((tile_pattern as i8) as i16) * 16;
+				0x9000u16.wrapping_add(adjusted as u16)
+			}, // $8800-97FF (signed, so we start in the middle)
+			true  => {
+				(tile_pattern as u16 * 16) + 0x8000
+			}, // $8000-8FFF (unsigned)
+		};
+
+		let mut tile_id = self.address_to_tile_id(vram_location);
+		if (attributes & Bit::Bit3 as u8) > 0 {
+			tile_id += TILES_PER_BANK;
+		}
+
+		// Refresh the tile if it has been overwritten in VRAM
+		if self.tile_cache[tile_id].dirty {
+			self.refresh_tile(tile_id);
+		}
+
+		let mut pixel_x = x % 8;
+		let mut pixel_y = y % 8;
+		if (attributes & Bit::Bit5 as u8) > 0 { pixel_x = 7 - pixel_x; }
+		if (attributes & Bit::Bit6 as u8) > 0 { pixel_y = 7 - pixel_y; }
+
+		let pixel = self.tile_cache[tile_id].pixels[((pixel_y * 8) + pixel_x) as usize];
+		(pixel, attributes)
+	}
+
+	// Colors a background/window pixel and records its priority for sprites
+	#[inline]
+	fn plot_background(&mut self, i: usize, pixel: u8, attributes: u8, bg_priority: &mut [u8]) {
+		let color = match self.cgb_mode {
+			true => self.cgb_colorize(&self.bg_palette_ram, attributes & 0x07, pixel),
+			false => self.colorize(pixel, self.BGP.get(), &self.dmg_palette.bg),
+		};
+		let mut flags = 0;
+		if pixel != 0 { flags |= BG_OPAQUE; }
+		if (attributes & Bit::Bit7 as u8) > 0 { flags |= BG_PRIORITY; }
+		bg_priority[i] = flags;
+		let offset = (self.LY.get() as usize * FRAME_WIDTH) + i;
+		self.frame_buffer[offset] = color;
+	}
+
+	#[inline]
+	fn draw_background(&mut self, bg_priority: &mut [u8]) {
+		// BG Tile Map Display Select
+		let tile_map_location = match self.LCDC.is_set(Bit::Bit3) {
+			true  => 0x9C00,
+			false => 0x9800,
+		};
+
+		let y = self.LY.get().wrapping_add(self.SCY.get());
+
+		for i in 0..FRAME_WIDTH {
+			let x = (i as u8).wrapping_add(self.SCX.get());
+			let (pixel, attributes) = self.map_pixel(tile_map_location, x, y);
+			self.plot_background(i, pixel, attributes, bg_priority);
+		}
+	}
+
+	// The window shows up once LY has matched WY at the start of a line, and stays
+	// until the end of the frame even if WY changes
+	fn check_window_y(&mut self) {
+		if self.LCDC.is_set(Bit::Bit5) && self.LY.get() == self.WY.get() {
+			self.window_y_triggered = true;
+		}
+	}
+
+	fn reset_window(&mut self) {
+		self.window_line = 0;
+		self.window_y_triggered = false;
+	}
+
+	#[inline]
+	fn draw_window(&mut self, bg_priority: &mut [u8]) {
+		// WX is the screen position plus 7, with WX above 166 the window is past the right edge
+		let start = self.WX.get() as i32 - 7;
+		if !self.window_y_triggered || start >= FRAME_WIDTH as i32 { return; }
+
+		let tile_map_location = match self.LCDC.is_set(Bit::Bit6) {
+			true  => 0x9C00,
+			false => 0x9800
+		};
+
+		// With WX below 7 the left part of the window is cut off
+		let y = self.window_line;
+		for i in cmp::max(start, 0) as usize..FRAME_WIDTH {
+			let x = (i as i32 - start) as u8;
+			let (pixel, attributes) = self.map_pixel(tile_map_location, x, y);
+			self.plot_background(i, pixel, attributes, bg_priority);
+		}
+		self.window_line = self.window_line.wrapping_add(1);
+	}
+
+	// Picks the sprites on the current line like the OAM scan in mode 2 does: the first 10 in OAM
+	// that cover the line. X isn't looked at, so sprites off the side of the screen still count.
+	fn scan_oam(&mut self) {
+		let ly = self.LY.get() as i32;
+		let height = match self.LCDC.is_set(Bit::Bit2) {
+			true => 16,
+			false => 8,
+		};
+		self.line_sprite_count = 0;
+		for (index, sprite) in self.sprite_table.iter().enumerate() {
+			if ly >= sprite.y_pos && ly < sprite.y_pos + height {
+				self.line_sprites[self.line_sprite_count] = index;
+				self.line_sprite_count += 1;
+				if self.line_sprite_count == SPRITES_PER_LINE {
+					break;
+				}
+			}
+		}
+	}
+
+	#[inline]
+	fn draw_sprites(&mut self, bg_priority: &mut [u8]) {
+		let scanline_y = self.LY.get();
+		// In CGB mode, clearing LCDC bit 0 puts sprites above the background regardless of priority
+		let master_priority = !self.cgb_mode || self.LCDC.is_set(Bit::Bit0);
+
+		self.scan_oam();
+		// Draw in order of priority. On CGB the sprite first in OAM wins, on DMG the
+		// sprite furthest to the left does and OAM order only breaks ties.
+		let mut sprites = self.line_sprites;
+		let sprites = &mut sprites[..self.line_sprite_count];
+		if !self.cgb_mode {
+			let sprite_table = &self.sprite_table;
+			sprites.sort_by_key(|&index| (sprite_table[index].x_pos, index));
+		}
+
+		// Set once a sprite has a pixel at a column. Sprites below it don't show there,
+		// even if the background covers the sprite on top.
+		let mut covered = [false; FRAME_WIDTH];
+
+		for &index in sprites.iter() {
+			let sprite = self.sprite_table[index].clone();
+			let pixels = self.sprite_pixels(&sprite, scanline_y);
+			let (palette, colors) = match sprite.use_palette_one {
+				false => (self.OBP0.get(), self.dmg_palette.obj0),
+				true  => (self.OBP1.get(), self.dmg_palette.obj1),
+			};
+
+			for pixel_x in 0..8 {
+				let x = sprite.x_pos + pixel_x as i32;
+
+				// Do not draw out of bounds sprites
+				if x < 0 || x >= FRAME_WIDTH as i32 { continue; }
+				let x = x as usize;
+
+				let pixel = pixels[pixel_x];
+				if pixel == 0 || covered[x] { continue; } // Color zero is ignored when drawing sprites
+				covered[x] = true;
+				// Do not draw over background priority
+				let bg = bg_priority[x];
+				if master_priority && (bg & BG_OPAQUE) > 0 {
+					if sprite.behind_background || (bg & BG_PRIORITY) > 0 {
+						continue;
+					}
+				}
+				let color = match self.cgb_mode {
+					true => self.cgb_colorize(&self.obj_palette_ram, sprite.cgb_palette, pixel),
+					false => self.colorize(pixel, palette, &colors),
+				};
+				let offset = (scanline_y as usize * FRAME_WIDTH) + x;
+				self.frame_buffer[offset] = color;
+			}
+		}
+	}
+
+	// Returns the shades of the row of a sprite that falls on a scanline, left to right on screen
+	fn sprite_pixels(&mut self, sprite: &SpriteEntry, scanline_y: u8) -> [u8; 8] {
+		let sprite_y = sprite.y_pos as u8;
+
+		let pixel_y = (scanline_y.wrapping_sub(sprite_y)) % 8;
+		let lookup_y = match sprite.y_flip {
+			true  => { ((pixel_y as i8 - 7) * -1) as u8 },
+			false => pixel_y
+		};
+
+		let tile_id = match self.LCDC.is_set(Bit::Bit2) {
+			true => {
+				// Are we displaying the top half or bottom half?
+				if (scanline_y.wrapping_sub(sprite_y) < 8) { // top half
+					if sprite.y_flip { sprite.tile_id | 0x01 }
+					else { sprite.tile_id & 0xFE }
+				} else { // bottom half
+					if sprite.y_flip { sprite.tile_id & 0xFE }
+					else { sprite.tile_id | 0x01 }
+				}
+			},
+			false => sprite.tile_id,
+		};
+		let mut tile_id = tile_id as usize;
+		// Attribute bit 3 only selects a VRAM bank on CGB
+		if self.cgb_mode && sprite.vram_bank == 1 {
+			tile_id += TILES_PER_BANK;
+		}
+
+		if self.tile_cache[tile_id].dirty {
+			self.refresh_tile(tile_id);
+		}
+
+		let tile = &self.tile_cache[tile_id];
+		let mut pixels = [0; 8];
+		for pixel_x in 0..8 {
+			// Flip the X/Y rendering if necessary
+			let lookup_x = match sprite.x_flip {
+				true  => ((pixel_x as i8 - 7) * -1) as u8,
+				false => pixel_x
+			};
+			pixels[pixel_x as usize] = tile.pixels[((lookup_y * 8) + lookup_x) as usize];
+		}
+		pixels
+	}
+
+	// Translates a location in VRAM to the relevant tile cache ID
+	#[inline]
+	fn address_to_tile_id(&self, address: u16) -> usize {
+		((address - VRAM_START) / 16) as usize
+	}
+
+	#[inline]
+	fn get_mode(&self) -> StatusMode {
+		let mode = self.STAT.get() & 0x3;
+		match mode {
+			0 => StatusMode::HBlank,
+			1 => StatusMode::VBlank,
+			2 => StatusMode::Oam,
+			3 => StatusMode::Transfer,
+			_ => unreachable!(),
+		}
+	}
+
+	#[inline]
+	fn set_mode(&mut self, mode: StatusMode) {
+		let mut stat = self.STAT.get() & !(0x3);
+		stat |= mode as u8;
+		self.STAT.set(stat);
+	}
+
+	// Reads raw data directly from VRAM
+	// This is necessary to bypass the memory access restrictions
+	// that are imposed on the CPU depending on LCD STAT register
+	#[inline]
+	fn read_raw(&self, address: u16) -> u8 {
+		self.Vram[(address - VRAM_START) as usize]
+	}
+
+	pub fn read(&self, address: u16) -> u8 {
+		match address {
+			VRAM_START ... VRAM_END => {
+				match self.get_mode() {
+					// Cannot access VRAM in Transfer Mode
+					StatusMode::Transfer => 0xFF,
+					_ => {
+						self.Vram[self.vram_index(address)]
+					},
+				}
+			},
+			OAM_START  ... OAM_END  => {
+				match self.get_mode() {
+					// Cannot access OAM in the following modes:
+					StatusMode::Transfer | StatusMode::Oam => 0xFF,
+					_ => {
+						self.Oam[(address - OAM_START) as usize]
+					},
+				}
+			},
+			VBK => self.vram_bank,
+			BCPS => self.BCPS.get(),
+			OCPS => self.OCPS.get(),
+			BCPD => self.bg_palette_ram[(self.BCPS.get() & 0x3F) as usize],
+			OCPD => self.obj_palette_ram[(self.OCPS.get() & 0x3F) as usize],
+			_ => unreachable!(),
+		}
+	}
+
+	// Location in VRAM for an address in the currently selected bank
+	#[inline]
+	fn vram_index(&self, address: u16) -> usize {
+		(self.vram_bank as usize * VRAM_BANK_SIZE) + (address - VRAM_START) as usize
+	}
+
+	// Writes to BCPD/OCPD go to the entry selected by BCPS/OCPS.
+	// If bit 7 of the index register is set it increments after every write.
+	fn write_palette(palette_ram: &mut Vec<u8>, index: &mut MemoryRegister, data: u8) {
+		let selected = index.get() & 0x3F;
+		palette_ram[selected as usize] = data;
+		if index.is_set(Bit::Bit7) {
+			index.set(0x80 | ((selected + 1) & 0x3F));
+		}
+	}
+
+	pub fn write(&mut self, address: u16, data: u8) {
+		let stat = self.LCDC.get();
+		match address {
+			BGP  => { self.BGP.set(data); },
+			OBP0 => { self.OBP0.set(data); },
+			OBP1 => { self.OBP1.set(data); },
+			LCDC => { self.update_lcdc(data); },
+			STAT => {
+				let stat = self.STAT.get();
+				let high = data & 0xF8;
+				let low = stat & 0x7; // Bits 0-2 are read only
+				self.STAT.set(high | low);
+			},
+			LYC => { self.LYC.set(data); },
+			SCY => { self.SCY.set(data); },
+			SCX => { self.SCX.set(data); },
+			WY => { self.WY.set(data); },
+			WX => { self.WX.set(data); },
+			VBK => { self.vram_bank = data & 0x01; },
+			BCPS => { self.BCPS.set(data & 0xBF); },
+			OCPS => { self.OCPS.set(data & 0xBF); },
+			BCPD => { Gpu::write_palette(&mut self.bg_palette_ram, &mut self.BCPS, data); },
+			OCPD => { Gpu::write_palette(&mut self.obj_palette_ram, &mut self.OCPS, data); },
+
+			VRAM_START ... VRAM_END => {
+				// Disallow writes to VRAM depending on the mode
+				if self.get_mode() == StatusMode::Transfer {
+					return;
+				}
+				let index = self.vram_index(address);
+				self.Vram[index] = data;
+				// Mark this data as dirty so the tile cache updates
+				if address <= TILE_RAM_END {
+					let tile_id = (self.vram_bank as usize * TILES_PER_BANK) + (address - VRAM_START) as usize / 16;
+					self.tile_cache[tile_id].dirty = true;
+				}
+			},
+
+			OAM_START ... OAM_END => {
+				match self.get_mode() {
+					StatusMode::Oam | StatusMode::Transfer => { return; },
+					_ => {
+						self.Oam[(address - OAM_START) as usize] = data;
+						self.update_sprite(address, data);
+					}
+				};
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	// Update the sprite table with the relevant new information
+	fn update_sprite(&mut self, address: u16, data: u8) {
+		let sprite_id = (address - OAM_START) / 4; // 4 bytes of information per sprite
+		let sprite = &mut self.sprite_table[sprite_id as usize];
+		let data_type = address % 4;
+		match data_type {
+			0 => sprite.y_pos = data as i32 - 16,
+			1 => sprite.x_pos = data as i32 - 8,
+			2 => sprite.tile_id = data,
+			3 => {
+				sprite.behind_background = (data & Bit::Bit7 as u8) > 0;
+				sprite.y_flip = (data & Bit::Bit6 as u8) > 0;
+				sprite.x_flip = (data & Bit::Bit5 as u8) > 0;
+				sprite.use_palette_one = (data & Bit::Bit4 as u8) > 0;
+				sprite.vram_bank = (data & Bit::Bit3 as u8) >> 3;
+				sprite.cgb_palette = data & 0x07;
+			},
+			_ => unreachable!()
+		};
+	}
+
+	fn update_lcdc(&mut self, data: u8) {
+		let new = MemoryRegister::new(data);
+
+		if !new.is_set(Bit::Bit7) && self.display_enabled() {
+			if self.get_mode() != StatusMode::VBlank {
+				//panic!("LCD off, but not in VBlank");
+			}
+			self.LY.clear();
+			// The first line starts from the beginning once the LCD is turned back on
+			self.scanline_cycles = 0;
+			self.frame_cycles = 0;
+			self.reset_window();
+			// Set stat mode to 0 to let game know it is safe to write to RAM
+			self.set_mode(StatusMode::HBlank);
+		}
+
+		// The first line after turning the LCD on starts in mode 0 instead of mode 2
+		if new.is_set(Bit::Bit7) && !self.display_enabled() {
+			self.first_line = true;
+			self.set_mode(StatusMode::HBlank);
+		}
+		self.LCDC.set(data);
+	}
+
+	// OAM DMA writes bypass the mode restrictions
+	pub fn write_oam_dma(&mut self, offset: u16, data: u8) {
+		self.Oam[offset as usize] = data;
+		self.update_sprite(OAM_START + offset, data);
+	}
+
+	// OAM is locked while the LCD is scanning it and drawing
+	pub fn oam_accessible(&self) -> bool {
+		match self.get_mode() {
+			StatusMode::Oam | StatusMode::Transfer => false,
+			_ => true,
+		}
+	}
+
+	// Mode 0 of a visible line, H-Blank DMA started now copies its first block right away
+	pub fn in_hblank(&self) -> bool {
+		self.display_enabled() && self.get_mode() == StatusMode::HBlank && (self.LY.get() as usize) < FRAME_HEIGHT
+	}
+
+	#[inline]
+	fn display_enabled(&self) -> bool {
+		self.LCDC.is_set(Bit::Bit7)
+	}
+
+	pub fn dump(&self) {
+		println!("DUMPING VRAM");
+		dump("vram.bin", &self.Vram);
+		dump("oam.bin", &self.Oam);
+	}
+}
+
+impl Stateful for Gpu {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bytes(&self.Vram);
+		state.write_bytes(&self.Oam);
+		self.LCDC.save_state(state);
+		self.STAT.save_state(state);
+		self.LYC.save_state(state);
+		self.LY.save_state(state);
+		self.BGP.save_state(state);
+		self.OBP0.save_state(state);
+		self.OBP1.save_state(state);
+		self.SCY.save_state(state);
+		self.SCX.save_state(state);
+		self.WY.save_state(state);
+		self.WX.save_state(state);
+		state.write_usize(self.scanline_cycles);
+		state.write_usize(self.frame_cycles);
+		state.write_u8(self.vram_bank);
+		state.write_bytes(&self.bg_palette_ram);
+		state.write_bytes(&self.obj_palette_ram);
+		self.BCPS.save_state(state);
+		self.OCPS.save_state(state);
+		state.write_bool(self.stat_line);
+		state.write_bool(self.first_line);
+		state.write_u8(self.window_line);
+		state.write_bool(self.window_y_triggered);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		state.read_into(&mut self.Vram)?;
+		state.read_into(&mut self.Oam)?;
+		self.LCDC.load_state(state)?;
+		self.STAT.load_state(state)?;
+		self.LYC.load_state(state)?;
+		self.LY.load_state(state)?;
+		self.BGP.load_state(state)?;
+		self.OBP0.load_state(state)?;
+		self.OBP1.load_state(state)?;
+		self.SCY.load_state(state)?;
+		self.SCX.load_state(state)?;
+		self.WY.load_state(state)?;
+		self.WX.load_state(state)?;
+		self.scanline_cycles = state.read_usize()?;
+		self.frame_cycles = state.read_usize()?;
+		// The position in the frame must agree with LY, which already reads 0 on the last line
+		let line = self.frame_cycles / HBLANK_PERIOD;
+		let ly = self.LY.get() as usize;
+		let ly_valid = ly == line || (line == LINES_PER_FRAME - 1 && ly == 0);
+		if line >= LINES_PER_FRAME || self.scanline_cycles != self.frame_cycles % HBLANK_PERIOD || !ly_valid {
+			return Err(StateError::InvalidValue);
+		}
+		self.vram_bank = state.read_u8()? & 0x01;
+		state.read_into(&mut self.bg_palette_ram)?;
+		state.read_into(&mut self.obj_palette_ram)?;
+		self.BCPS.load_state(state)?;
+		self.OCPS.load_state(state)?;
+		self.stat_line = state.read_bool()?;
+		self.first_line = state.read_bool()?;
+		self.window_line = state.read_u8()?;
+		self.window_y_triggered = state.read_bool()?;
+
+		// The caches are derived from VRAM and OAM, rebuild them
+		for tile in self.tile_cache.iter_mut() {
+			tile.dirty = true;
+		}
+		for index in 0..OAM_SIZE {
+			let data = self.Oam[index];
+			self.update_sprite(OAM_START + index as u16, data);
+		}
+		// The pixel FIFO isn't saved, a line that was being drawn starts over
+		self.start_transfer();
+		Ok(())
+	}
+}
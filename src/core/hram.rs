@@ -1,5 +1,7 @@
-// 352 bytes of High RAM
-pub const HRAM_SIZE: usize = 352;
+use core::state::*;
+
+// 127 bytes of High RAM at FF80-FFFE
+pub const HRAM_SIZE: usize = 127;
 
 pub struct Hram {
 	bytes: Vec<u8>
@@ -23,4 +25,14 @@ impl Hram {
 	pub fn write(&mut self, address: u16, data: u8) {
 		self.bytes[address as usize] = data;
 	}
-}
\ No newline at end of file
+}
+
+impl Stateful for Hram {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bytes(&self.bytes);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		state.read_into(&mut self.bytes)
+	}
+}
@@ -0,0 +1,104 @@
+use core::memory_map::*;
+use super::*;
+
+// IO registers at FF00-FF7F, plus IE at FFFF.
+// Every register is routed to the hardware it belongs to. Bits a register doesn't have
+// read as 1, and addresses without a register read as 0xFF and ignore writes.
+
+// Bits that always read as 1. Sound and serial registers apply their own masks,
+// since they depend on the channel and the model.
+fn unused_bits(address: u16) -> u8 {
+	match address {
+		P1   => 0xC0,
+		TAC  => 0xF8,
+		IF   => 0xE0,
+		STAT => 0x80,
+		KEY1 => 0x7E,
+		VBK  => 0xFE,
+		BCPS | OCPS => 0x40,
+		SVBK => 0xF8,
+		_ => 0x00,
+	}
+}
+
+impl Interconnect {
+	pub(super) fn read_io(&self, address: u16) -> u8 {
+		let value = match address {
+			P1 =>   self.joypad.read(),
+			SB | SC => self.serial.read(address),
+			DIV =>  self.timer.read_div(),
+			TIMA => self.timer.read_counter(),
+			TMA =>  self.timer.read_modulo(),
+			TAC =>  self.timer.read_control(),
+			IF =>   self.interrupt.IF.get(),
+			NR10 ... WAVE_RAM_END => self.apu.read(address),
+			LCDC => self.gpu.LCDC.get(),
+			STAT => self.gpu.STAT.get(),
+			SCY =>  self.gpu.SCY.get(),
+			SCX =>  self.gpu.SCX.get(),
+			LY =>   self.gpu.LY.get(),
+			LYC =>  self.gpu.LYC.get(),
+			BGP =>  self.gpu.BGP.get(),
+			OBP0 => self.gpu.OBP0.get(),
+			OBP1 => self.gpu.OBP1.get(),
+			WY =>   self.gpu.WY.get(),
+			WX =>   self.gpu.WX.get(),
+			OAM_DMA => self.oam_dma.read(),
+			KEY1 if self.cgb_mode => self.read_speed(),
+			VBK | BCPS | BCPD | OCPS | OCPD if self.cgb_mode => self.gpu.read(address),
+			// The source and destination registers are write only
+			HDMA5 if self.cgb_mode => self.vram_dma.read(address),
+			SVBK if self.cgb_mode => self.wram.read_bank(),
+			IE =>   self.interrupt.IE.get(),
+			_ => 0xFF,
+		};
+		value | unused_bits(address)
+	}
+
+	fn read_speed(&self) -> u8 {
+		let speed = match self.double_speed { true => 0x80, false => 0 };
+		let prepare = match self.prepare_speed_switch { true => 0x01, false => 0 };
+		speed | prepare
+	}
+
+	pub(super) fn write_io(&mut self, address: u16, data: u8) {
+		match address {
+			P1 => self.joypad.write(data),
+			SB | SC => self.serial.write(address, data),
+			DIV => self.timer.write_div(data),
+			TIMA => self.timer.write_counter(data),
+			TMA => self.timer.write_modulo(data),
+			TAC => self.timer.write_control(data),
+			IF => self.interrupt.write(address, data),
+			NR10 ... WAVE_RAM_END => self.apu.write(address, data),
+			BGP | OBP0 | OBP1 | SCY | SCX | WX | WY => self.gpu.write(address, data),
+			// These can change the level of the STAT interrupt line right away
+			LCDC | STAT | LYC => {
+				self.gpu.write(address, data);
+				self.gpu.update_stat_line(&mut self.interrupt);
+			},
+			OAM_DMA => self.oam_dma.request(data),
+			// Once unmapped the boot ROM stays gone until the next power cycle
+			BOOT => if (data & 0x01) > 0 {
+				self.boot_rom = None;
+			},
+			KEY1 if self.cgb_mode => self.prepare_speed_switch = (data & 0x01) > 0,
+			VBK | BCPS | BCPD | OCPS | OCPD if self.cgb_mode => self.gpu.write(address, data),
+			HDMA1 ... HDMA5 if self.cgb_mode => {
+				// General purpose DMA copies everything before the CPU continues
+				let blocks = self.vram_dma.write(address, data);
+				for _ in 0..blocks {
+					self.copy_vram_block();
+				}
+				// H-Blank DMA started during H-Blank doesn't wait for the next one
+				if address == HDMA5 && self.vram_dma.hblank_active && self.gpu.in_hblank() {
+					self.copy_vram_block();
+				}
+			},
+			SVBK if self.cgb_mode => self.wram.write_bank(data),
+			IE => self.interrupt.write(address, data),
+			// LY is read only, everything else isn't there
+			_ => {},
+		}
+	}
+}
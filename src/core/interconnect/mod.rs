@@ -0,0 +1,388 @@
+use core::rom::*;
+use core::wram::*;
+use core::hram::*;
+use core::gpu::*;
+use core::sink::*;
+use core::interrupt::*;
+use core::memory_map::*;
+use core::joypad::*;
+use core::dma::*;
+use core::timer::*;
+use core::apu::*;
+use core::serial::*;
+use core::boot::*;
+use core::debugger::*;
+use std::cell::RefCell;
+use std::mem;
+use core::state::*;
+use core::palette::*;
+
+mod io;
+
+pub struct Interconnect {
+	rom: Rom,
+	// Mapped over the cartridge until a write to BOOT
+	boot_rom: Option<BootRom>,
+	wram: Wram,
+	hram: Hram,
+	oam_dma: OamDma,
+	vram_dma: VramDma,
+	// Cycles the CPU has to wait for VRAM DMA copies to finish
+	dma_stall: usize,
+	timer: Timer,
+	pub apu: Apu,
+	pub gpu: Gpu,
+	pub interrupt: InterruptHandler,
+	pub joypad: Joypad,
+	pub serial: Serial,
+	// Memory accesses matching a watchpoint are recorded for the debugger
+	watchpoints: Vec<Watchpoint>,
+	watch_hits: RefCell<Vec<WatchHit>>,
+	cgb_mode: bool,
+	double_speed: bool,
+	prepare_speed_switch: bool,
+	// Machine cycles run by bus accesses during the current instruction
+	ticks: usize,
+	// Output produced mid-instruction, handed to the caller's sinks once the instruction is done
+	video_sink: VideoSink,
+	audio_sink: AudioSink,
+}
+
+impl Interconnect {
+	pub fn new(_rom: Rom, model: Model, boot_rom: Option<BootRom>) -> Interconnect {
+		// Bit 7 of the CGB flag marks a game with color support
+		let cgb_mode = model == Model::Cgb && (_rom.cgb_flag() & 0x80) > 0;
+		let boot = boot_rom.is_some();
+		let mut interconnect = Interconnect {
+			rom: _rom,
+			boot_rom: boot_rom,
+			gpu: Gpu::new(cgb_mode),
+			wram: Wram::new(),
+			hram: Hram::new(),
+			timer: Timer::new(),
+			apu: Apu::new(),
+			oam_dma: OamDma::new(),
+			vram_dma: VramDma::new(),
+			dma_stall: 0,
+			interrupt: InterruptHandler::new(),
+			joypad: Joypad::new(),
+			serial: Serial::new(cgb_mode),
+			watchpoints: Vec::new(),
+			watch_hits: RefCell::new(Vec::new()),
+			cgb_mode: cgb_mode,
+			double_speed: false,
+			prepare_speed_switch: false,
+			ticks: 0,
+			video_sink: VideoSink::new(),
+			audio_sink: AudioSink::new(),
+		};
+		// A CGB colors DMG games in, unless it is told otherwise
+		if model == Model::Cgb && !cgb_mode {
+			let palette = cgb_boot_palette(&interconnect.rom);
+			interconnect.gpu.set_dmg_palette(palette);
+		}
+		// Without a boot ROM, start in the state it would have left behind
+		if !boot {
+			interconnect.post_boot(model);
+		}
+		interconnect
+	}
+
+	fn post_boot(&mut self, model: Model) {
+		for &(address, value) in POST_BOOT_IO.iter() {
+			self.write(address, value);
+		}
+		self.timer.set_div(model.post_boot_div());
+		// V-Blank with LY == LYC
+		self.gpu.STAT.set(0x85);
+	}
+
+	pub fn boot_rom_mapped(&self) -> bool {
+		self.boot_rom.is_some()
+	}
+
+	pub fn cgb_mode(&self) -> bool {
+		self.cgb_mode
+	}
+
+	pub fn double_speed(&self) -> bool {
+		self.double_speed
+	}
+
+	// Called on STOP. Returns true if it switched CPU speed,
+	// otherwise the machine enters low power mode and DIV is reset.
+	pub fn stop(&mut self) -> bool {
+		if self.switch_speed() {
+			return true;
+		}
+		self.timer.write_div(0);
+		false
+	}
+
+	// Toggles CPU speed if a switch was prepared through KEY1.
+	// Returns true if the speed was changed.
+	pub fn switch_speed(&mut self) -> bool {
+		if !self.cgb_mode || !self.prepare_speed_switch {
+			return false;
+		}
+		self.double_speed = !self.double_speed;
+		self.prepare_speed_switch = false;
+		true
+	}
+
+	pub fn rom(&self) -> &Rom {
+		&self.rom
+	}
+
+	pub fn rom_mut(&mut self) -> &mut Rom {
+		&mut self.rom
+	}
+
+	pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+		self.watchpoints = watchpoints;
+	}
+
+	pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+		self.watch_hits.replace(Vec::new())
+	}
+
+	fn watch(&self, address: u16, value: u8, access: Access) {
+		for watchpoint in self.watchpoints.iter().filter(|w| w.matches(address, access)) {
+			self.watch_hits.borrow_mut().push(WatchHit {
+				id: watchpoint.id,
+				address: address,
+				value: value,
+				access: access,
+			});
+		}
+	}
+
+	pub fn read(&self, address: u16) -> u8 {
+		let value = self.read_memory(address);
+		if !self.watchpoints.is_empty() {
+			self.watch(address, value, Access::Read);
+		}
+		value
+	}
+
+	// Reads without triggering watchpoints, for tools looking at memory
+	pub fn peek(&self, address: u16) -> u8 {
+		self.read_memory(address)
+	}
+
+	pub fn write(&mut self, address: u16, data: u8) {
+		if !self.watchpoints.is_empty() {
+			self.watch(address, data, Access::Write);
+		}
+		self.write_memory(address, data);
+	}
+
+	fn read_memory(&self, address: u16) -> u8 {
+		match address {
+			ROM_START  ... ROM_BANK_END  => match self.boot_rom {
+				Some(ref boot_rom) if boot_rom.is_mapped(address) => boot_rom.read(address),
+				_ => self.rom.read(address),
+			},
+			VRAM_START ... VRAM_END => self.gpu.read(address),
+			ERAM_START ... ERAM_END => self.rom.read(address),
+			WRAM_START ... WRAM_END => self.wram.read(address - WRAM_START),
+			ECHO_START ... ECHO_END => self.wram.read(address - ECHO_START),
+			OAM_START  ... OAM_END  => self.gpu.read(address),
+			// Reads as 0x00, unless the LCD is using OAM
+			UNUSABLE_START ... UNUSABLE_END => match self.gpu.oam_accessible() {
+				true => 0x00,
+				false => 0xFF,
+			},
+			IO_START ... IO_END | IE => self.read_io(address),
+			HRAM_START ... HRAM_END => self.hram.read(address - HRAM_START),
+		}
+	}
+
+	fn write_memory(&mut self, address: u16, data: u8) {
+		match address {
+			ROM_START  ... ROM_BANK_END  => self.rom.write(address, data),
+			VRAM_START ... VRAM_END => self.gpu.write(address, data),
+			ERAM_START ... ERAM_END => self.rom.write(address, data),
+			WRAM_START ... WRAM_END => self.wram.write(address - WRAM_START, data),
+			ECHO_START ... ECHO_END => {
+				// Note: Use of the area from 0xE000 to 0xFDFF is prohibited.
+				self.wram.write(address - ECHO_START, data);
+				//panic!("Attempt to write to ECHO RAM");
+			},
+			OAM_START  ... OAM_END  => self.gpu.write(address, data),
+			IO_START ... IO_END | IE => self.write_io(address, data),
+			HRAM_START ... HRAM_END => self.hram.write(address - HRAM_START, data),
+			_ => {}
+		}
+	}
+
+	// Runs the rest of the machine for one machine cycle. The CPU calls this for
+	// every bus access and internal cycle, so hardware sees accesses at the right time.
+	pub fn tick(&mut self) {
+		self.ticks += 1;
+		self.run_cycles(4);
+	}
+
+	// A bus read by the CPU, takes one machine cycle
+	pub fn read_cycle(&mut self, address: u16) -> u8 {
+		self.tick();
+		match self.oam_dma.conflict(address, self.cgb_mode) {
+			Some(value) => value,
+			None => self.read(address),
+		}
+	}
+
+	// A bus write by the CPU, takes one machine cycle
+	pub fn write_cycle(&mut self, address: u16, data: u8) {
+		self.tick();
+		if self.oam_dma.conflict(address, self.cgb_mode).is_none() {
+			self.write(address, data);
+		}
+	}
+
+	// Runs whatever is left of an instruction that takes cycles machine cycles
+	// and returns how many it really took.
+	pub fn finish_instruction(&mut self, cycles: usize) -> usize {
+		while self.ticks < cycles {
+			self.tick();
+		}
+		let ticks = self.ticks;
+		self.ticks = 0;
+		ticks
+	}
+
+	// Runs the machine for a number of clock cycles without the CPU
+	pub fn run_cycles(&mut self, cycles: usize) {
+		let mut video_sink = mem::replace(&mut self.video_sink, VideoSink::new());
+		let mut audio_sink = mem::replace(&mut self.audio_sink, AudioSink::new());
+		self.cycles(cycles, &mut video_sink, &mut audio_sink);
+		self.video_sink = video_sink;
+		self.audio_sink = audio_sink;
+	}
+
+	// Hands over the frame and samples produced since the last call
+	pub fn flush_sinks(&mut self, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) {
+		if let Some(frame) = self.video_sink.take() {
+			video_sink.append(frame);
+		}
+		audio_sink.append_from(&mut self.audio_sink);
+	}
+
+	// Take the latest number of machine cycles and keep other hardware in sync.
+	// In double speed mode the timer and DMA run with the CPU, while video and sound don't.
+	fn cycles(&mut self, cycles: usize, video_sink: &mut VideoSink, audio_sink: &mut AudioSink) {
+		let normal_cycles = match self.double_speed {
+			true => cycles / 2,
+			false => cycles,
+		};
+		self.gpu.cycles(normal_cycles, &mut self.interrupt, video_sink);
+		self.timer.cycles(cycles, &mut self.interrupt);
+		self.serial.cycles(cycles, &mut self.interrupt);
+		self.apu.cycles(normal_cycles, audio_sink);
+		self.perform_dma(cycles);
+	}
+
+	// Returns how long the CPU has been halted by VRAM DMA since the last call
+	pub fn take_dma_stall(&mut self) -> usize {
+		let stall = self.dma_stall;
+		self.dma_stall = 0;
+		stall
+	}
+
+	fn perform_dma(&mut self, cycles: usize) {
+		// H-Blank DMA, one block at the start of every H-Blank
+		if self.gpu.take_hblank() && self.vram_dma.hblank_active {
+			self.copy_vram_block();
+		}
+
+
+		// OAM DMA runs with the CPU and writes OAM even while the LCD is using it
+		for _ in 0..cycles / 4 {
+			if let Some((from, offset)) = self.oam_dma.step() {
+				let value = self.read_dma_source(from);
+				self.oam_dma.latch(value);
+				self.gpu.write_oam_dma(offset, value);
+			}
+		}
+	}
+
+	// Pages E0-FF can't reach past echo RAM, they mirror the end of work RAM instead
+	fn read_dma_source(&self, address: u16) -> u8 {
+		match address {
+			ECHO_START ... 0xFFFF => self.wram.read(address - ECHO_START),
+			_ => self.read_memory(address),
+		}
+	}
+
+	fn copy_vram_block(&mut self) {
+		let (from, to) = self.vram_dma.next_block();
+		for offset in 0..VRAM_DMA_BLOCK_SIZE {
+			let value = self.read(from.wrapping_add(offset));
+			self.gpu.write(to + offset, value);
+		}
+		// The transfer takes the same time in double speed mode, so twice as many CPU cycles
+		self.dma_stall += match self.double_speed {
+			true => VRAM_DMA_BLOCK_CYCLES * 2,
+			false => VRAM_DMA_BLOCK_CYCLES,
+		};
+	}
+}
+
+impl Stateful for Interconnect {
+	fn save_state(&self, state: &mut StateWriter) {
+		self.rom.save_state(state);
+		match self.boot_rom {
+			Some(ref boot_rom) => {
+				state.write_bool(true);
+				state.write_u8(boot_rom.model() as u8);
+				state.write_bytes(boot_rom.bytes());
+			},
+			None => state.write_bool(false),
+		}
+		self.wram.save_state(state);
+		self.hram.save_state(state);
+		self.oam_dma.save_state(state);
+		self.vram_dma.save_state(state);
+		self.timer.save_state(state);
+		self.apu.save_state(state);
+		self.gpu.save_state(state);
+		self.interrupt.save_state(state);
+		self.joypad.save_state(state);
+		self.serial.save_state(state);
+		state.write_bool(self.double_speed);
+		state.write_bool(self.prepare_speed_switch);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.rom.load_state(state)?;
+		self.boot_rom = match state.read_bool()? {
+			true => {
+				let model = match state.read_u8()? {
+					0 => Model::Dmg,
+					1 => Model::Mgb,
+					2 => Model::Cgb,
+					_ => return Err(StateError::InvalidValue),
+				};
+				let bytes = state.read_bytes()?;
+				match BootRom::from_bytes(bytes, model) {
+					Ok(boot_rom) => Some(boot_rom),
+					Err(_) => return Err(StateError::InvalidValue),
+				}
+			},
+			false => None,
+		};
+		self.wram.load_state(state)?;
+		self.hram.load_state(state)?;
+		self.oam_dma.load_state(state)?;
+		self.vram_dma.load_state(state)?;
+		self.timer.load_state(state)?;
+		self.apu.load_state(state)?;
+		self.gpu.load_state(state)?;
+		self.interrupt.load_state(state)?;
+		self.joypad.load_state(state)?;
+		self.serial.load_state(state)?;
+		self.double_speed = state.read_bool()?;
+		self.prepare_speed_switch = state.read_bool()?;
+		Ok(())
+	}
+}
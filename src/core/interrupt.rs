@@ -1,5 +1,6 @@
 use core::helper::*;
 use core::memory_map::*;
+use core::state::*;
 
 pub enum InterruptFlag {
 	VBlank = 0b00000001,
@@ -20,7 +21,8 @@ pub enum InterruptVector {
 
 pub struct InterruptHandler {
 
-	counter: u32, // counts the number of opcodes since interrupt status changed
+	// EI sets IME after the instruction that follows it, counts instructions until then
+	enable_delay: u8,
 
 	master_enable: bool,
 	pub IE: MemoryRegister, // used to control intterupts
@@ -30,19 +32,46 @@ pub struct InterruptHandler {
 impl InterruptHandler {
 	pub fn new() -> InterruptHandler {
 		InterruptHandler {
-			counter: 0,
+			enable_delay: 0,
 			master_enable: false,
-			IE: MemoryRegister::new(0xFA),
+			IE: MemoryRegister::new(0x00),
 			IF: MemoryRegister::new(0x00),
 		}
 	}
 
 	pub fn enable(&mut self) {
 		self.master_enable = true;
+		self.enable_delay = 0;
+	}
+
+	pub fn enable_delayed(&mut self) {
+		if !self.master_enable && self.enable_delay == 0 {
+			self.enable_delay = 2;
+		}
 	}
 
 	pub fn disable(&mut self) {
 		self.master_enable = false;
+		self.enable_delay = 0;
+	}
+
+	// Called after every instruction
+	pub fn update_enable_delay(&mut self) {
+		if self.enable_delay > 0 {
+			self.enable_delay -= 1;
+			if self.enable_delay == 0 {
+				self.master_enable = true;
+			}
+		}
+	}
+
+	pub fn master_enabled(&self) -> bool {
+		self.master_enable
+	}
+
+	// Interrupts that are both requested and enabled, whether IME is set or not
+	pub fn pending(&self) -> u8 {
+		self.IE.get() & self.IF.get() & 0x1F
 	}
 
 	pub fn read(&mut self, address: u16) -> u8 {
@@ -72,6 +101,7 @@ impl InterruptHandler {
 				if interrupt_enable & bit > 0 {
 					if interrupt_flag & bit > 0 {
 						self.IF.set(interrupt_flag & !bit);
+						self.master_enable = false;
 						address = match bit {
 							0b00000001 => Some(InterruptVector::VBlank),
 							0b00000010 => Some(InterruptVector::Lcdc),
@@ -95,3 +125,20 @@ impl InterruptHandler {
 		self.IF.set(register);
 	}
 }
+
+impl Stateful for InterruptHandler {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.enable_delay);
+		state.write_bool(self.master_enable);
+		self.IE.save_state(state);
+		self.IF.save_state(state);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.enable_delay = state.read_u8()?;
+		self.master_enable = state.read_bool()?;
+		self.IE.load_state(state)?;
+		self.IF.load_state(state)?;
+		Ok(())
+	}
+}
@@ -1,5 +1,6 @@
 use core::helper::*;
 use core::interrupt::*;
+use core::state::*;
 
 pub const BUTTON_A: u8      = 0b00000001;
 pub const BUTTON_B: u8      = 0b00000010;
@@ -10,6 +11,19 @@ pub const PAD_LEFT: u8      = 0b00000010;
 pub const PAD_UP: u8        = 0b00000100;
 pub const PAD_DOWN: u8      = 0b00001000;
 
+// Every input on the console, used by front-ends to drive the joypad
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Right,
+    Left,
+    Up,
+    Down,
+}
+
 #[derive(Debug)]
 enum ReadInput {
     Directional,
@@ -53,6 +67,20 @@ impl Joypad {
         }
     }
 
+    // Sets the state of any input, routing it to the buttons or the directional pad
+    pub fn set_pressed(&mut self, interrupt: &mut InterruptHandler, button: Button, is_pressed: bool) {
+        match button {
+            Button::A      => self.set_button_pressed(interrupt, BUTTON_A, is_pressed),
+            Button::B      => self.set_button_pressed(interrupt, BUTTON_B, is_pressed),
+            Button::Select => self.set_button_pressed(interrupt, BUTTON_SELECT, is_pressed),
+            Button::Start  => self.set_button_pressed(interrupt, BUTTON_START, is_pressed),
+            Button::Right  => self.set_direction_pressed(interrupt, PAD_RIGHT, is_pressed),
+            Button::Left   => self.set_direction_pressed(interrupt, PAD_LEFT, is_pressed),
+            Button::Up     => self.set_direction_pressed(interrupt, PAD_UP, is_pressed),
+            Button::Down   => self.set_direction_pressed(interrupt, PAD_DOWN, is_pressed),
+        }
+    }
+
     pub fn read(&self) -> u8 {
         let mut result = self.register.get() & 0x30;
         let pad = match self.read_next {
@@ -74,3 +102,25 @@ impl Joypad {
         }
     }
 }
+
+impl Stateful for Joypad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(match self.read_next {
+            ReadInput::Directional => true,
+            ReadInput::Buttons => false,
+        });
+        state.write_u8(self.button_state);
+        state.write_u8(self.directional_state);
+        self.register.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.read_next = match state.read_bool()? {
+            true => ReadInput::Directional,
+            false => ReadInput::Buttons,
+        };
+        self.button_state = state.read_u8()?;
+        self.directional_state = state.read_u8()?;
+        self.register.load_state(state)
+    }
+}
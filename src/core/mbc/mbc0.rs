@@ -1,12 +1,19 @@
 use core::mbc::*;
+use core::state::*;
 
 pub struct MBC0;
 
 impl MemoryController for MBC0 {
 	fn read(&self, bytes: &Vec<u8>, address: u16) -> u8 {
-		bytes[address as usize]
+		// Without a controller there is no cartridge RAM to answer
+		bytes.get(address as usize).cloned().unwrap_or(0xFF)
 	}
 	fn write(&mut self, address: u16, data: u8) {}
     fn set_title(&mut self, name: String) {}
     fn load(&mut self) {}
 }
+
+impl Stateful for MBC0 {
+	fn save_state(&self, _state: &mut StateWriter) {}
+	fn load_state(&mut self, _state: &mut StateReader) -> Result<(), StateError> { Ok(()) }
+}
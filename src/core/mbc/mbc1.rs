@@ -1,6 +1,7 @@
 use core::mbc::*;
 use core::memory_map::*;
 use core::helper::*;
+use core::state::*;
 
 enum ModeSelect {
 	Rom,
@@ -48,7 +49,7 @@ impl MemoryController for MBC1 {
 			ROM_BANK_START ... ROM_BANK_END => {
 				let index = address - ROM_BANK_START;
 				let offset = (0x4000 as u32 * self.rom_bank as u32) + index as u32;
-				bytes[offset as usize]
+				bytes[offset as usize % bytes.len()]
 			},
 			ERAM_START ... ERAM_END => {
                 if !self.ram_enabled { return 0xFF; }
@@ -128,3 +129,28 @@ impl Drop for MBC1 {
         dump(&filename, &self.eram);
     }
 }
+
+impl Stateful for MBC1 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(match self.mode {
+            ModeSelect::Rom => false,
+            ModeSelect::Ram => true,
+        });
+        state.write_bytes(&self.eram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u8()?;
+        self.ram_bank = state.read_u8()? & 0x03;
+        self.ram_enabled = state.read_bool()?;
+        self.mode = match state.read_bool()? {
+            false => ModeSelect::Rom,
+            true => ModeSelect::Ram,
+        };
+        state.read_into(&mut self.eram)?;
+        Ok(())
+    }
+}
@@ -1,26 +1,107 @@
 use core::mbc::*;
+use core::memory_map::*;
+use core::helper::*;
+use core::state::*;
+
+// MBC2 has 512 x 4 bits of RAM built into the controller itself
+const RAM_SIZE: usize = 512;
 
 pub struct MBC2 {
-	rom_bank: u8
+    title: String,
+    rom_bank: u8,
+    ram_enabled: bool,
+    has_battery: bool,
+    ram: Vec<u8>,
 }
 
 impl MBC2 {
-	pub fn new() -> MBC2 {
-		MBC2 {
-			rom_bank: 0
-		}
-	}
+    pub fn new(has_battery: bool) -> MBC2 {
+        MBC2 {
+            title: "".to_owned(),
+            rom_bank: 0x01,
+            ram_enabled: false,
+            has_battery: has_battery,
+            ram: vec![0; RAM_SIZE],
+        }
+    }
 }
 
 impl MemoryController for MBC2 {
-	fn read(&self, bytes: &Vec<u8>, address: u16) -> u8 {
-		bytes[address as usize]
-	}
-	fn write(&mut self, address: u16, data: u8) {
-		unimplemented!();
-	}
+
+    fn read(&self, bytes: &Vec<u8>, address: u16) -> u8 {
+        match address {
+            ROM_START ... ROM_END => {
+                bytes[address as usize]
+            },
+            ROM_BANK_START ... ROM_BANK_END => {
+                let bank = self.rom_bank as usize * 0x4000;
+                let offset = (address - ROM_BANK_START) as usize;
+                bytes[(bank + offset) % bytes.len()]
+            },
+            ERAM_START ... ERAM_END => {
+                if !self.ram_enabled { return 0xFF; }
+                // Only the lower 9 bits are decoded, so the RAM repeats through A000-BFFF.
+                // The upper nibble is not connected and reads back as 1s.
+                let index = (address & 0x01FF) as usize;
+                0xF0 | self.ram[index]
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            // Bit 8 of the address selects between RAM enable and ROM bank number
+            0x0000 ... 0x3FFF => {
+                if (address & 0x0100) == 0 {
+                    self.ram_enabled = (data & 0x0F) == 0x0A;
+                } else {
+                    let bank = data & 0x0F;
+                    self.rom_bank = if bank == 0 { 1 } else { bank };
+                }
+            },
+            0x4000 ... 0x7FFF => {},
+            ERAM_START ... ERAM_END => {
+                if !self.ram_enabled { return; }
+                let index = (address & 0x01FF) as usize;
+                self.ram[index] = data & 0x0F;
+            },
+            _ => unreachable!(),
+        }
+    }
+
     fn set_title(&mut self, name: String) {
-        unimplemented!();
+        self.title = name;
+    }
+
+    fn load(&mut self) {
+        if !self.has_battery { return; }
+        let mut title = self.title.clone();
+        title.push_str(".sav");
+        load(title, &mut self.ram);
+        self.ram.resize(RAM_SIZE, 0);
+    }
+}
+
+impl Stateful for MBC2 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_bool(self.ram_enabled);
+        state.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u8()? & 0x0F;
+        self.ram_enabled = state.read_bool()?;
+        state.read_into(&mut self.ram)
+    }
+}
+
+impl Drop for MBC2 {
+    fn drop(&mut self) {
+        if !self.has_battery { return; }
+        let mut filename = self.title.clone();
+        filename.push_str(".sav");
+        dump(&filename, &self.ram);
     }
-    fn load(&mut self) { unimplemented!(); }
 }
@@ -1,6 +1,8 @@
 use core::mbc::*;
+use core::mbc::rtc::*;
 use core::memory_map::*;
 use core::helper::*;
+use core::state::*;
 
 pub struct MBC3 {
     title: String,
@@ -9,20 +11,44 @@ pub struct MBC3 {
     rtc_register: u8,
     ram_timer_enable: bool,
     select_ram_bank: bool,
+    has_battery: bool,
     eram: Vec<u8>,
+    rtc: Option<Rtc>,
 }
 
 impl MBC3 {
-    pub fn new() -> MBC3 {
+    // RAM is sized like the header says, so the .sav matches what other emulators write
+    pub fn new(has_timer: bool, has_battery: bool, ram_size: usize) -> MBC3 {
         MBC3 {
             title: "".to_owned(),
             rom_bank: 0x01,
             ram_bank: 0x00,
             rtc_register: 0x00,
             ram_timer_enable: false,
-            select_ram_bank: false,
-            eram: vec![0; 0x8000],
+            select_ram_bank: true,
+            has_battery: has_battery,
+            eram: vec![0; ram_size],
+            rtc: match has_timer {
+                true => Some(Rtc::new()),
+                false => None,
+            },
+        }
+    }
+
+    // Small RAM chips are mirrored across the banks, reads without RAM return None
+    fn eram_index(&self, address: u16) -> Option<usize> {
+        if self.eram.is_empty() {
+            return None;
         }
+        let bank = self.ram_bank as usize * 0x2000;
+        let offset = (address - ERAM_START) as usize;
+        Some((bank + offset) % self.eram.len())
+    }
+
+    fn save_path(&self) -> String {
+        let mut filename = self.title.clone();
+        filename.push_str(".sav");
+        filename
     }
 }
 
@@ -37,15 +63,23 @@ impl MemoryController for MBC3 {
                 let bank = self.rom_bank as usize * 0x4000;
                 let offset = address - ROM_BANK_START;
                 let index = bank + offset as usize; 
-                bytes[index]
+                bytes[index % bytes.len()]
             },
             ERAM_START ... ERAM_END => {
-                if self.ram_timer_enable {
-                    let offset = address - ERAM_START;
-                    let bank = self.ram_bank as usize * 0x2000;
-                    let index = bank + offset as usize;
-                    self.eram[index]
-                } else { 0x00 } // May be 0xFF
+                if !self.ram_timer_enable {
+                    return 0xFF;
+                }
+                if self.select_ram_bank {
+                    match self.eram_index(address) {
+                        Some(index) => self.eram[index],
+                        None => 0xFF,
+                    }
+                } else {
+                    match self.rtc {
+                        Some(ref rtc) => rtc.read(self.rtc_register),
+                        None => 0xFF,
+                    }
+                }
             },
             _ => { unreachable!(); }
         }
@@ -55,7 +89,7 @@ impl MemoryController for MBC3 {
         match address {
             0x0000 ... 0x1FFF => {
                 // RAM/Timer enable write
-                self.ram_timer_enable = match data {
+                self.ram_timer_enable = match data & 0x0F {
                     0x0A => true,
                     _ => false,
                 };
@@ -68,25 +102,34 @@ impl MemoryController for MBC3 {
             },
             0x4000 ... 0x5FFF => {
                 // RAM Bank number or RTC Register Select write
-                // We only select the RAM bank for now
-                if self.ram_timer_enable { // This may not be necessary
-                    let bank = data & 0x3;
-
-                    // We only care about the RAM bank at the moment
-                    if bank <= 3 {
-                        self.ram_bank = bank;
-                    }
+                match data {
+                    0x00 ... 0x03 => {
+                        self.ram_bank = data;
+                        self.select_ram_bank = true;
+                    },
+                    0x08 ... 0x0C => {
+                        self.rtc_register = data;
+                        self.select_ram_bank = false;
+                    },
+                    _ => {},
                 }
             },
             0x6000 ... 0x7FFF => {
-                // Latch Clock Data write   
+                // Latch Clock Data write
+                if let Some(ref mut rtc) = self.rtc {
+                    rtc.write_latch(data);
+                }
             },
             ERAM_START ... ERAM_END => {
-                if self.ram_timer_enable {
-                    let offset = address - ERAM_START;
-                    let bank = self.ram_bank as usize * 0x2000;
-                    let index = bank + offset as usize;
-                    self.eram[index] = data;
+                if !self.ram_timer_enable {
+                    return;
+                }
+                if self.select_ram_bank {
+                    if let Some(index) = self.eram_index(address) {
+                        self.eram[index] = data;
+                    }
+                } else if let Some(ref mut rtc) = self.rtc {
+                    rtc.write(self.rtc_register, data);
                 }
             },
             _ => { unreachable!(); }
@@ -97,17 +140,71 @@ impl MemoryController for MBC3 {
         self.title = name;
     }
 
+    // The clock state is stored after the RAM in the .sav file
     fn load(&mut self) {
-        let mut title = self.title.clone();
-        title.push_str(".sav");
-        load(title, &mut self.eram);
+        if !self.has_battery { return; }
+        let mut buffer = Vec::new();
+        load(self.save_path(), &mut buffer);
+        if buffer.is_empty() { return; }
+
+        let trailer = match buffer.len() % 0x400 {
+            RTC_TRAILER_SIZE => RTC_TRAILER_SIZE,
+            RTC_TRAILER_SIZE_SHORT => RTC_TRAILER_SIZE_SHORT,
+            _ => 0,
+        };
+        let ram_size = buffer.len() - trailer;
+        // A trailer that can't be read leaves the clock at its defaults
+        if let Some(ref mut rtc) = self.rtc {
+            if trailer > 0 {
+                let _ = rtc.load_trailer(&buffer[ram_size..]);
+            }
+        }
+        buffer.truncate(ram_size);
+        buffer.resize(self.eram.len(), 0);
+        self.eram = buffer;
+    }
+
+    fn set_rtc_clock(&mut self, clock: Box<RtcClock>) {
+        if let Some(ref mut rtc) = self.rtc {
+            rtc.set_clock(clock);
+        }
+    }
+}
+
+impl Stateful for MBC3 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_u8(self.rtc_register);
+        state.write_bool(self.ram_timer_enable);
+        state.write_bool(self.select_ram_bank);
+        state.write_bytes(&self.eram);
+        if let Some(ref rtc) = self.rtc {
+            rtc.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u8()? & 0x7F;
+        self.ram_bank = state.read_u8()? & 0x03;
+        self.rtc_register = state.read_u8()?;
+        self.ram_timer_enable = state.read_bool()?;
+        self.select_ram_bank = state.read_bool()?;
+        state.read_into(&mut self.eram)?;
+        if let Some(ref mut rtc) = self.rtc {
+            rtc.load_state(state)?;
+        }
+        Ok(())
     }
 }
 
 impl Drop for MBC3 {
     fn drop(&mut self) {
-        let mut filename = self.title.clone();
-        filename.push_str(".sav");
-        dump(&filename, &self.eram);
+        if !self.has_battery { return; }
+        let mut data = self.eram.clone();
+        if let Some(ref mut rtc) = self.rtc {
+            data.extend(rtc.to_trailer());
+        }
+        dump(&self.save_path(), &data);
     }
 }
@@ -0,0 +1,160 @@
+use core::mbc::*;
+use core::memory_map::*;
+use core::helper::*;
+use core::state::*;
+
+pub struct MBC5 {
+    title: String,
+    rom_bank: u16, // 9 bits
+    ram_bank: u8,
+    ram_enabled: bool,
+    has_rumble: bool,
+    has_battery: bool,
+    rumble: bool,
+    rumble_callback: Option<RumbleCallback>,
+    eram: Vec<u8>,
+}
+
+impl MBC5 {
+    // Up to 16 banks of 8KB, sized like the header says
+    pub fn new(has_rumble: bool, has_battery: bool, ram_size: usize) -> MBC5 {
+        MBC5 {
+            title: "".to_owned(),
+            rom_bank: 0x01,
+            ram_bank: 0x00,
+            ram_enabled: false,
+            has_rumble: has_rumble,
+            has_battery: has_battery,
+            rumble: false,
+            rumble_callback: None,
+            eram: vec![0; ram_size],
+        }
+    }
+
+    // Bank numbers wrap around the size of the RAM, there is nothing to read without any
+    fn eram_index(&self, address: u16) -> Option<usize> {
+        if self.eram.is_empty() {
+            return None;
+        }
+        let bank = self.ram_bank as usize * 0x2000;
+        let offset = (address - ERAM_START) as usize;
+        Some((bank + offset) % self.eram.len())
+    }
+
+    fn set_rumble(&mut self, rumble: bool) {
+        if self.rumble == rumble {
+            return;
+        }
+        self.rumble = rumble;
+        if let Some(ref mut callback) = self.rumble_callback {
+            callback(rumble);
+        }
+    }
+}
+
+impl MemoryController for MBC5 {
+
+    fn read(&self, bytes: &Vec<u8>, address: u16) -> u8 {
+        match address {
+            ROM_START ... ROM_END => {
+                bytes[address as usize]
+            },
+            ROM_BANK_START ... ROM_BANK_END => {
+                let bank = self.rom_bank as usize * 0x4000;
+                let offset = (address - ROM_BANK_START) as usize;
+                // Bank numbers wrap around the size of the ROM
+                bytes[(bank + offset) % bytes.len()]
+            },
+            ERAM_START ... ERAM_END => {
+                if !self.ram_enabled { return 0xFF; }
+                match self.eram_index(address) {
+                    Some(index) => self.eram[index],
+                    None => 0xFF,
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address {
+            // RAM enable
+            0x0000 ... 0x1FFF => {
+                self.ram_enabled = (data & 0x0F) == 0x0A;
+            },
+            // Lower 8 bits of the ROM bank number. Unlike MBC1, bank 0 can be selected.
+            0x2000 ... 0x2FFF => {
+                self.rom_bank = (self.rom_bank & 0x100) | data as u16;
+            },
+            // 9th bit of the ROM bank number
+            0x3000 ... 0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (((data & 0x01) as u16) << 8);
+            },
+            // RAM bank number. On rumble carts bit 3 drives the motor instead.
+            0x4000 ... 0x5FFF => {
+                if self.has_rumble {
+                    self.ram_bank = data & 0x07;
+                    self.set_rumble((data & 0x08) > 0);
+                } else {
+                    self.ram_bank = data & 0x0F;
+                }
+            },
+            0x6000 ... 0x7FFF => {},
+            ERAM_START ... ERAM_END => {
+                if !self.ram_enabled { return; }
+                if let Some(index) = self.eram_index(address) {
+                    self.eram[index] = data;
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_title(&mut self, name: String) {
+        self.title = name;
+    }
+
+    fn load(&mut self) {
+        if !self.has_battery || self.eram.is_empty() { return; }
+        let size = self.eram.len();
+        let mut title = self.title.clone();
+        title.push_str(".sav");
+        load(title, &mut self.eram);
+        // The .sav may be empty or come from a cartridge with a different amount of RAM
+        self.eram.resize(size, 0);
+    }
+
+    fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.rumble_callback = Some(callback);
+    }
+}
+
+impl Stateful for MBC5 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.rumble);
+        state.write_bytes(&self.eram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.rom_bank = state.read_u16()? & 0x1FF;
+        self.ram_bank = state.read_u8()? & 0x0F;
+        self.ram_enabled = state.read_bool()?;
+        let rumble = state.read_bool()?;
+        self.set_rumble(rumble);
+        state.read_into(&mut self.eram)?;
+        Ok(())
+    }
+}
+
+impl Drop for MBC5 {
+    fn drop(&mut self) {
+        // Nothing to keep on carts without RAM
+        if !self.has_battery || self.eram.is_empty() { return; }
+        let mut filename = self.title.clone();
+        filename.push_str(".sav");
+        dump(&filename, &self.eram);
+    }
+}
@@ -2,10 +2,35 @@ pub mod mbc0;
 pub mod mbc1;
 pub mod mbc2;
 pub mod mbc3;
+pub mod mbc5;
+pub mod rtc;
 
-pub trait MemoryController {
+use core::state::*;
+use self::rtc::*;
+
+// Invoked with the new motor state whenever a rumble cartridge turns its motor on or off
+pub type RumbleCallback = Box<FnMut(bool)>;
+
+// Bank state and external RAM are part of save states
+pub trait MemoryController: Stateful {
 	fn read(&self, bytes: &Vec<u8>, address: u16) -> u8;
 	fn write(&mut self, address: u16, data: u8);
     fn set_title(&mut self, name: String);
     fn load(&mut self);
+    // Only cartridges with a rumble motor make use of this
+    fn set_rumble_callback(&mut self, _callback: RumbleCallback) {}
+    // Only cartridges with a real time clock make use of this
+    fn set_rtc_clock(&mut self, _clock: Box<RtcClock>) {}
+}
+
+// Size in bytes of the external RAM given by the RAM size code in the cartridge header
+pub fn eram_size(code: u8) -> usize {
+    match code {
+        0x01 => 0x800,
+        0x02 => 0x2000,
+        0x03 => 0x8000,
+        0x04 => 0x20000,
+        0x05 => 0x10000,
+        _ => 0,
+    }
 }
@@ -0,0 +1,303 @@
+use std::rc::Rc;
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+use core::state::*;
+
+// Size of the RTC data appended to .sav files. This is the layout used by
+// VBA-M, BGB and most other emulators: 5 live registers and 5 latched registers
+// as 32-bit values, followed by a 64-bit UNIX timestamp. Older saves use a 32-bit timestamp.
+pub const RTC_TRAILER_SIZE: usize = 48;
+pub const RTC_TRAILER_SIZE_SHORT: usize = 44;
+
+// Source of wall clock time in seconds, so the clock can be faked
+pub trait RtcClock {
+	fn now(&self) -> u64;
+}
+
+// Real time from the host
+pub struct SystemClock;
+
+impl RtcClock for SystemClock {
+	fn now(&self) -> u64 {
+		match SystemTime::now().duration_since(UNIX_EPOCH) {
+			Ok(duration) => duration.as_secs(),
+			Err(_) => 0,
+		}
+	}
+}
+
+// A clock that only moves when told to. Clones share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+	time: Rc<Cell<u64>>,
+}
+
+impl ManualClock {
+	pub fn new(time: u64) -> ManualClock {
+		ManualClock {
+			time: Rc::new(Cell::new(time)),
+		}
+	}
+
+	pub fn set(&self, time: u64) {
+		self.time.set(time);
+	}
+
+	pub fn advance(&self, seconds: u64) {
+		self.time.set(self.time.get() + seconds);
+	}
+}
+
+impl RtcClock for ManualClock {
+	fn now(&self) -> u64 {
+		self.time.get()
+	}
+}
+
+// MBC3 real time clock
+// 08: Seconds, 09: Minutes, 0A: Hours, 0B: Day counter low
+// 0C: Bit 0 day counter high bit, Bit 6 halt, Bit 7 day counter carry
+pub struct Rtc {
+	seconds: u8,
+	minutes: u8,
+	hours: u8,
+	days: u16,
+	halted: bool,
+	day_carry: bool,
+	latched: [u8; 5],
+	latch_ready: bool, // 0x00 was written, a 0x01 write latches the clock
+	last_time: u64,
+	clock: Box<RtcClock>,
+}
+
+impl Rtc {
+	pub fn new() -> Rtc {
+		let clock = SystemClock;
+		Rtc {
+			seconds: 0,
+			minutes: 0,
+			hours: 0,
+			days: 0,
+			halted: false,
+			day_carry: false,
+			latched: [0; 5],
+			latch_ready: false,
+			last_time: clock.now(),
+			clock: Box::new(clock),
+		}
+	}
+
+	pub fn set_clock(&mut self, clock: Box<RtcClock>) {
+		self.update();
+		self.last_time = clock.now();
+		self.clock = clock;
+	}
+
+	// Catch up with the time that has passed since the last update
+	pub fn update(&mut self) {
+		let now = self.clock.now();
+		if !self.halted && now > self.last_time {
+			let elapsed = now - self.last_time;
+			self.advance(elapsed);
+		}
+		self.last_time = now;
+	}
+
+	fn advance(&mut self, elapsed: u64) {
+		let seconds = self.seconds as u64 + elapsed;
+		self.seconds = (seconds % 60) as u8;
+		let minutes = self.minutes as u64 + seconds / 60;
+		self.minutes = (minutes % 60) as u8;
+		let hours = self.hours as u64 + minutes / 60;
+		self.hours = (hours % 24) as u8;
+		let days = self.days as u64 + hours / 24;
+		if days > 0x1FF {
+			self.day_carry = true;
+		}
+		self.days = (days % 0x200) as u16;
+	}
+
+	fn registers(&self) -> [u8; 5] {
+		let mut control = ((self.days >> 8) & 0x01) as u8;
+		if self.halted { control |= 0x40; }
+		if self.day_carry { control |= 0x80; }
+		[self.seconds, self.minutes, self.hours, (self.days & 0xFF) as u8, control]
+	}
+
+	// Live registers followed by the latched registers
+	fn set_registers(&mut self, values: &[u8; 10]) {
+		self.seconds = values[0] & 0x3F;
+		self.minutes = values[1] & 0x3F;
+		self.hours = values[2] & 0x1F;
+		self.days = (((values[4] & 0x01) as u16) << 8) | values[3] as u16;
+		self.halted = (values[4] & 0x40) > 0;
+		self.day_carry = (values[4] & 0x80) > 0;
+		self.latched.copy_from_slice(&values[5..]);
+	}
+
+	// Writing 0x00 then 0x01 copies the clock into the latched registers
+	pub fn write_latch(&mut self, data: u8) {
+		if self.latch_ready && data == 0x01 {
+			self.update();
+			self.latched = self.registers();
+		}
+		self.latch_ready = data == 0x00;
+	}
+
+	// Reads return the latched value of the selected register
+	pub fn read(&self, register: u8) -> u8 {
+		match register {
+			0x08 ... 0x0C => self.latched[(register - 0x08) as usize],
+			_ => 0xFF,
+		}
+	}
+
+	pub fn write(&mut self, register: u8, data: u8) {
+		self.update();
+		match register {
+			0x08 => self.seconds = data & 0x3F,
+			0x09 => self.minutes = data & 0x3F,
+			0x0A => self.hours = data & 0x1F,
+			0x0B => self.days = (self.days & 0x100) | data as u16,
+			0x0C => {
+				self.days = (self.days & 0xFF) | (((data & 0x01) as u16) << 8);
+				self.halted = (data & 0x40) > 0;
+				self.day_carry = (data & 0x80) > 0;
+			},
+			_ => {},
+		}
+	}
+
+	// Serializes the clock in the common .sav trailer format
+	pub fn to_trailer(&mut self) -> Vec<u8> {
+		self.update();
+		let mut trailer = StateWriter::new();
+		for &value in self.registers().iter().chain(self.latched.iter()) {
+			trailer.write_u32(value as u32);
+		}
+		trailer.write_u64(self.last_time);
+		trailer.consume()
+	}
+
+	// Restores the clock from a .sav trailer and catches up with the time
+	// that passed while the emulator was closed
+	pub fn load_trailer(&mut self, trailer: &[u8]) -> Result<(), StateError> {
+		let mut reader = StateReader::new(trailer);
+		let mut values = [0; 10];
+		for value in values.iter_mut() {
+			*value = reader.read_u32()? as u8;
+		}
+		let timestamp = match trailer.len() {
+			RTC_TRAILER_SIZE => reader.read_u64()?,
+			_ => reader.read_u32()? as u64,
+		};
+		self.set_registers(&values);
+		self.last_time = timestamp;
+		self.update();
+		Ok(())
+	}
+}
+
+impl Stateful for Rtc {
+	fn save_state(&self, state: &mut StateWriter) {
+		for &value in self.registers().iter().chain(self.latched.iter()) {
+			state.write_u8(value);
+		}
+		state.write_bool(self.latch_ready);
+		state.write_u64(self.last_time);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		let mut values = [0; 10];
+		for value in values.iter_mut() {
+			*value = state.read_u8()?;
+		}
+		self.latch_ready = state.read_bool()?;
+		let last_time = state.read_u64()?;
+		self.set_registers(&values);
+		self.last_time = last_time;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use core::mbc::rtc::*;
+
+	fn rtc_at(clock: &ManualClock) -> Rtc {
+		let mut rtc = Rtc::new();
+		rtc.set_clock(Box::new(clock.clone()));
+		rtc
+	}
+
+	fn latch(rtc: &mut Rtc) -> [u8; 5] {
+		rtc.write_latch(0x00);
+		rtc.write_latch(0x01);
+		let mut registers = [0; 5];
+		for (i, register) in registers.iter_mut().enumerate() {
+			*register = rtc.read(0x08 + i as u8);
+		}
+		registers
+	}
+
+	#[test]
+	fn seconds_roll_over_into_minutes() {
+		let clock = ManualClock::new(1000);
+		let mut rtc = rtc_at(&clock);
+		rtc.write(0x08, 59);
+		clock.advance(1);
+		assert_eq!(latch(&mut rtc), [0, 1, 0, 0, 0x00]);
+	}
+
+	#[test]
+	fn day_counter_overflow_sets_carry() {
+		let clock = ManualClock::new(1000);
+		let mut rtc = rtc_at(&clock);
+		rtc.write(0x08, 59);
+		rtc.write(0x09, 59);
+		rtc.write(0x0A, 23);
+		rtc.write(0x0B, 0xFF);
+		rtc.write(0x0C, 0x01);
+		clock.advance(1);
+		assert_eq!(latch(&mut rtc), [0, 0, 0, 0, 0x80]);
+		// The carry stays set until it is cleared by the game
+		clock.advance(60 * 60 * 24);
+		assert_eq!(latch(&mut rtc), [0, 0, 0, 1, 0x80]);
+	}
+
+	#[test]
+	fn reads_only_change_when_latched() {
+		let clock = ManualClock::new(1000);
+		let mut rtc = rtc_at(&clock);
+		assert_eq!(latch(&mut rtc)[0], 0);
+		clock.advance(5);
+		assert_eq!(rtc.read(0x08), 0);
+		// Only 0x00 followed by 0x01 latches
+		rtc.write_latch(0x01);
+		assert_eq!(rtc.read(0x08), 0);
+		rtc.write_latch(0x00);
+		rtc.write_latch(0x01);
+		assert_eq!(rtc.read(0x08), 5);
+	}
+
+	#[test]
+	fn trailer_round_trip() {
+		let clock = ManualClock::new(1000);
+		let mut rtc = rtc_at(&clock);
+		rtc.write(0x09, 30);
+		rtc.write(0x0C, 0x40);
+		latch(&mut rtc);
+		rtc.write(0x0C, 0x00);
+		clock.advance(10);
+		let trailer = rtc.to_trailer();
+		assert_eq!(trailer.len(), RTC_TRAILER_SIZE);
+
+		// Time keeps passing while the emulator is closed
+		clock.advance(50);
+		let mut loaded = rtc_at(&clock);
+		loaded.load_trailer(&trailer).unwrap();
+		assert_eq!(loaded.read(0x09), 30);
+		assert_eq!(loaded.read(0x0C), 0x40);
+		assert_eq!(latch(&mut loaded), [0, 31, 0, 0, 0x00]);
+	}
+}
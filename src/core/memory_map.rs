@@ -28,7 +28,8 @@ pub const NR44: u16 = 0xFF23; // Sound Mode 4 counter/consecutive
 pub const NR50: u16 = 0xFF24; // Channel Control/Volume
 pub const NR51: u16 = 0xFF25; // Sound output terminal
 pub const NR52: u16 = 0xFF26; // Sound on/off
-// FF30 - FF3F Wave pattern RAM
+pub const WAVE_RAM_START: u16 = 0xFF30; // Wave pattern RAM
+pub const WAVE_RAM_END: u16   = 0xFF3F;
 pub const LCDC: u16    = 0xFF40; // LCD Control
 pub const STAT: u16    = 0xFF41; // LCD Status
 pub const SCY: u16     = 0xFF42; // Scroll Y
@@ -41,6 +42,19 @@ pub const OBP0: u16    = 0xFF48; // Object Palette 0 data
 pub const OBP1: u16    = 0xFF49; // Object Palette 1 data
 pub const WY: u16      = 0xFF4A; // Window Y position
 pub const WX: u16      = 0xFF4B; // Window X position
+pub const KEY1: u16    = 0xFF4D; // CGB Prepare speed switch
+pub const VBK: u16     = 0xFF4F; // CGB VRAM bank
+pub const BOOT: u16    = 0xFF50; // Boot ROM disable
+pub const HDMA1: u16   = 0xFF51; // CGB VRAM DMA source, high
+pub const HDMA2: u16   = 0xFF52; // CGB VRAM DMA source, low
+pub const HDMA3: u16   = 0xFF53; // CGB VRAM DMA destination, high
+pub const HDMA4: u16   = 0xFF54; // CGB VRAM DMA destination, low
+pub const HDMA5: u16   = 0xFF55; // CGB VRAM DMA length/mode/start
+pub const BCPS: u16    = 0xFF68; // CGB Background palette index
+pub const BCPD: u16    = 0xFF69; // CGB Background palette data
+pub const OCPS: u16    = 0xFF6A; // CGB Sprite palette index
+pub const OCPD: u16    = 0xFF6B; // CGB Sprite palette data
+pub const SVBK: u16    = 0xFF70; // CGB WRAM bank
 pub const IE: u16      = 0xFFFF; // Interrupt Enable
 
 // 16 KB ROM Bank 00 (in cartridge, fixed at bank 00)
@@ -70,6 +84,14 @@ pub const ECHO_END: u16   = 0xFDFF;
 pub const OAM_START: u16 = 0xFE00;
 pub const OAM_END: u16   = 0xFE9F;
 
-// 352 bytes of High RAM
-pub const HRAM_START: u16 = 0xFEA0;
-pub const HRAM_END: u16   = 0xFFFF;
+// Not usable, reads return 0x00 or 0xFF depending on the LCD mode
+pub const UNUSABLE_START: u16 = 0xFEA0;
+pub const UNUSABLE_END: u16   = 0xFEFF;
+
+// IO registers, IE at 0xFFFF is handled with them
+pub const IO_START: u16 = 0xFF00;
+pub const IO_END: u16   = 0xFF7F;
+
+// 127 bytes of High RAM
+pub const HRAM_START: u16 = 0xFF80;
+pub const HRAM_END: u16   = 0xFFFE;
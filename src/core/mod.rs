@@ -18,3 +18,11 @@ pub mod sink;
 pub mod joypad;
 pub mod dma;
 pub mod timer;
+pub mod apu;
+pub mod serial;
+pub mod boot;
+pub mod debugger;
+pub mod trace;
+pub mod state;
+pub mod error;
+pub mod palette;
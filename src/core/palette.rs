@@ -0,0 +1,174 @@
+use std::fs;
+use core::rom::*;
+use core::error::*;
+
+// Colors used to draw DMG games. The four shades a palette register picks from are
+// given from lightest to darkest, separately for the background and both sprite palettes.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DmgPalette {
+	pub bg: [u32; 4],
+	pub obj0: [u32; 4],
+	pub obj1: [u32; 4],
+}
+
+impl DmgPalette {
+	// The same four colors for everything
+	pub fn uniform(colors: [u32; 4]) -> DmgPalette {
+		DmgPalette {
+			bg: colors,
+			obj0: colors,
+			obj1: colors,
+		}
+	}
+
+	// Looks up a built-in preset by name, see PRESETS
+	pub fn preset(name: &str) -> Option<DmgPalette> {
+		PRESETS.iter().find(|&&(preset, _)| preset == name).map(|&(_, palette)| palette)
+	}
+
+	// Loads a palette from a text file with one line per layer: bg, obj0, obj1 or all,
+	// followed by four colors as hex RGB, lightest first. Lines starting with # are ignored.
+	pub fn load(path: &str) -> Result<DmgPalette, EmuError> {
+		let text = fs::read_to_string(path)?;
+		let mut palette = DEFAULT_PALETTE;
+		for (number, line) in text.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let invalid = EmuError::InvalidPalette(number + 1);
+			let mut words = line.split_whitespace();
+			let layer = words.next().unwrap();
+			let mut colors = [0; 4];
+			for color in colors.iter_mut() {
+				*color = match words.next().map(|word| u32::from_str_radix(word.trim_start_matches('#'), 16)) {
+					Some(Ok(value)) if value <= 0xFFFFFF => value,
+					_ => return Err(invalid),
+				};
+			}
+			if words.next().is_some() {
+				return Err(invalid);
+			}
+			match layer {
+				"bg" => palette.bg = colors,
+				"obj0" => palette.obj0 = colors,
+				"obj1" => palette.obj1 = colors,
+				"all" => palette = DmgPalette::uniform(colors),
+				_ => return Err(invalid),
+			}
+		}
+		Ok(palette)
+	}
+}
+
+pub const DEFAULT_PALETTE: DmgPalette = DmgPalette {
+	bg: GREY,
+	obj0: GREY,
+	obj1: GREY,
+};
+
+const GREY: [u32; 4] = [0xEEEEEE, 0x999999, 0x666666, 0x222222];
+
+const WHITE_RED: [u32; 4] = [0xFFFFFF, 0xFF8584, 0x943A3A, 0x000000];
+const WHITE_BROWN: [u32; 4] = [0xFFFFFF, 0xFFAD63, 0x843100, 0x000000];
+const WHITE_GREEN: [u32; 4] = [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000];
+const WHITE_BLUE: [u32; 4] = [0xFFFFFF, 0x65A49B, 0x0000FE, 0x000000];
+
+// Built-in palettes, the ones named after buttons are picked on a CGB by holding
+// them while the boot logo is shown
+pub const PRESETS: [(&'static str, DmgPalette); 16] = [
+	("grey", DEFAULT_PALETTE),
+	// Green tinted LCD of the original Game Boy
+	("dmg", DmgPalette {
+		bg: [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F],
+		obj0: [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F],
+		obj1: [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F],
+	}),
+	("pocket", DmgPalette {
+		bg: [0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F],
+		obj0: [0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F],
+		obj1: [0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F],
+	}),
+	// Backlit Game Boy Light
+	("light", DmgPalette {
+		bg: [0x00B581, 0x009A71, 0x00694A, 0x004F3B],
+		obj0: [0x00B581, 0x009A71, 0x00694A, 0x004F3B],
+		obj1: [0x00B581, 0x009A71, 0x00694A, 0x004F3B],
+	}),
+	("up", DmgPalette { bg: WHITE_BROWN, obj0: WHITE_BROWN, obj1: WHITE_BROWN }),
+	("up-a", DmgPalette { bg: WHITE_RED, obj0: WHITE_RED, obj1: WHITE_RED }),
+	("up-b", DmgPalette {
+		bg: [0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108],
+		obj0: [0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108],
+		obj1: [0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108],
+	}),
+	("left", DmgPalette { bg: WHITE_BLUE, obj0: WHITE_RED, obj1: WHITE_RED }),
+	("left-a", DmgPalette { bg: [0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000], obj0: WHITE_RED, obj1: WHITE_BROWN }),
+	("left-b", DmgPalette {
+		bg: [0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000],
+		obj0: [0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000],
+		obj1: [0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000],
+	}),
+	("down", DmgPalette {
+		bg: [0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000],
+		obj0: [0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000],
+		obj1: [0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000],
+	}),
+	("down-a", DmgPalette {
+		bg: [0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000],
+		obj0: [0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000],
+		obj1: [0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000],
+	}),
+	("down-b", DmgPalette { bg: [0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000], obj0: WHITE_BLUE, obj1: WHITE_GREEN }),
+	("right", DmgPalette {
+		bg: [0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000],
+		obj0: [0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000],
+		obj1: [0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000],
+	}),
+	// What the CGB boot ROM uses for games it doesn't know
+	("right-a", DmgPalette { bg: [0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000], obj0: WHITE_RED, obj1: WHITE_RED }),
+	("right-b", DmgPalette {
+		bg: [0x000000, 0x008484, 0xFFDE00, 0xFFFFFF],
+		obj0: [0x000000, 0x008484, 0xFFDE00, 0xFFFFFF],
+		obj1: [0x000000, 0x008484, 0xFFDE00, 0xFFFFFF],
+	}),
+];
+
+// Palettes the CGB boot ROM gives Nintendo games, by the sum of the title bytes
+const GAME_PALETTES: [(u8, DmgPalette); 3] = [
+	// POKEMON RED
+	(0x14, DmgPalette { bg: WHITE_RED, obj0: WHITE_GREEN, obj1: WHITE_RED }),
+	// POKEMON BLUE
+	(0x61, DmgPalette { bg: [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000], obj0: WHITE_RED, obj1: [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000] }),
+	// ZELDA
+	(0x70, DmgPalette { bg: WHITE_RED, obj0: [0xFFFFFF, 0x00FF00, 0x318400, 0x004A00], obj1: [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000] }),
+];
+
+// The palette a CGB picks for a DMG game when no buttons are held during boot
+pub fn cgb_boot_palette(rom: &Rom) -> DmgPalette {
+	let default = DmgPalette::preset("right-a").unwrap();
+	// Only games published by Nintendo are looked up
+	let nintendo = match rom.read(0x14B) {
+		0x01 => true,
+		0x33 => rom.read(0x144) == b'0' && rom.read(0x145) == b'1',
+		_ => false,
+	};
+	if !nintendo {
+		return default;
+	}
+	let checksum = (0x134..0x144).fold(0u8, |sum, address| sum.wrapping_add(rom.read(address)));
+	GAME_PALETTES.iter()
+		.find(|&&(game, _)| game == checksum)
+		.map(|&(_, palette)| palette)
+		.unwrap_or(default)
+}
+
+// Maps a 15-bit CGB color to RGB the way it looks on the CGB LCD, which is less saturated
+// and mixes the channels a bit, instead of scaling each channel on its own
+pub fn correct_color(red: u32, green: u32, blue: u32) -> u32 {
+	let r = (red * 26 + green * 4 + blue * 2).min(960) >> 2;
+	let g = (green * 24 + blue * 8).min(960) >> 2;
+	let b = (red * 6 + green * 4 + blue * 22).min(960) >> 2;
+	(r << 16) | (g << 8) | b
+}
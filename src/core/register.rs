@@ -1,5 +1,6 @@
 use std::fmt;
 use core::helper::*;
+use core::state::*;
 
 pub enum Flag {
 	Zero      = 0b10000000,
@@ -28,18 +29,19 @@ pub struct Registers {
 impl Registers {
 
 	pub fn new() -> Registers {
-		// Registers are set to these specific values after GB BIOS runs
+		// Registers at power on, before the boot ROM runs.
+		// See Model::post_boot_registers for the state the boot ROM leaves behind.
 		Registers {
-			a: 0x01,
-			f: 0xB0,
+			a: 0x00,
+			f: 0x00,
 			b: 0x00,
-			c: 0x13,
+			c: 0x00,
 			d: 0x00,
-			e: 0xD8,
-			h: 0x01,
-			l: 0x4D,
-			sp: 0xFFFE,
-			pc: 0x0100,
+			e: 0x00,
+			h: 0x00,
+			l: 0x00,
+			sp: 0x0000,
+			pc: 0x0000,
 		}
 	}
 
@@ -108,6 +110,31 @@ impl Registers {
 
 }
 
+impl Stateful for Registers {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_u16(self.af());
+		state.write_u16(self.bc());
+		state.write_u16(self.de());
+		state.write_u16(self.hl());
+		state.write_u16(self.sp);
+		state.write_u16(self.pc);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		let af = state.read_u16()?;
+		self.set_af(af);
+		let bc = state.read_u16()?;
+		self.set_bc(bc);
+		let de = state.read_u16()?;
+		self.set_de(de);
+		let hl = state.read_u16()?;
+		self.set_hl(hl);
+		self.sp = state.read_u16()?;
+		self.pc = state.read_u16()?;
+		Ok(())
+	}
+}
+
 impl fmt::Display for Registers {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "Registers:
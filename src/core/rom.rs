@@ -4,6 +4,13 @@ use std::fs::File;
 use std::io::Read;
 use std::fmt;
 use core::mbc::*;
+use core::mbc::rtc::*;
+use core::state::*;
+use core::error::*;
+
+// Everything up to the global checksum
+const HEADER_END: usize = 0x150;
+const MIN_ROM_SIZE: usize = 0x8000;
 
 pub struct Rom {
 	bytes: Vec<u8>,
@@ -13,26 +20,48 @@ pub struct Rom {
 impl Rom {
 
 	// Load a ROM image from a given path
-	pub fn load(path: String) -> Rom {
+	pub fn load(path: String) -> Result<Rom, EmuError> {
 		let mut buffer = Vec::new();
-		let mut file = File::open(path).expect("Invalid ROM path");
-		file.read_to_end(&mut buffer).expect("Unable to read ROM");
+		let mut file = File::open(path)?;
+		file.read_to_end(&mut buffer)?;
+		Rom::from_bytes(buffer)
+	}
+
+	// Build a ROM from an image already in memory
+	pub fn from_bytes(mut buffer: Vec<u8>) -> Result<Rom, EmuError> {
+		if buffer.len() < HEADER_END {
+			return Err(EmuError::RomTooSmall(buffer.len()));
+		}
+		// The smallest cartridges have 32KB of ROM, nothing drives the bus for the rest of it
+		if buffer.len() < MIN_ROM_SIZE {
+			buffer.resize(MIN_ROM_SIZE, 0xFF);
+		}
 		let cart_type = buffer[0x147];
+		let ram_size = eram_size(buffer[0x149]);
 
 		let mut cart = Rom {
 			controller: match cart_type {
 				0x00 => Box::new(mbc0::MBC0),
 				0x01 ... 0x03 => Box::new(mbc1::MBC1::new()),
-				0x05 ... 0x06 => Box::new(mbc2::MBC2::new()),
-				0x0F ... 0x13 => Box::new(mbc3::MBC3::new()),
-				_ => panic!("Unsupported Cartridge Type: ${:02X}", cart_type)
+				0x05 ... 0x06 => Box::new(mbc2::MBC2::new(cart_type == 0x06)),
+				0x0F ... 0x13 => {
+					let timer = cart_type <= 0x10;
+					let battery = cart_type == 0x0F || cart_type == 0x10 || cart_type == 0x13;
+					Box::new(mbc3::MBC3::new(timer, battery, ram_size))
+				},
+				0x19 ... 0x1E => {
+					let rumble = cart_type >= 0x1C;
+					let battery = cart_type == 0x1B || cart_type == 0x1E;
+					Box::new(mbc5::MBC5::new(rumble, battery, ram_size))
+				},
+				_ => return Err(EmuError::UnsupportedCartridge(cart_type))
 			},
 			bytes: buffer
 		};
         let name = cart.name();
         cart.controller.set_title(name);
         cart.controller.load();
-        cart
+        Ok(cart)
 	}
 
 	pub fn read(&self, address: u16) -> u8 {
@@ -43,6 +72,14 @@ impl Rom {
 		self.controller.write(address, data);
 	}
 
+	pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+		self.controller.set_rumble_callback(callback);
+	}
+
+	pub fn set_rtc_clock(&mut self, clock: Box<RtcClock>) {
+		self.controller.set_rtc_clock(clock);
+	}
+
 	pub fn size(&self) -> usize {
 		self.bytes.len()
 	}
@@ -67,6 +104,14 @@ impl Rom {
 		self.read(0x149)
 	}
 
+	pub fn header_checksum(&self) -> u8 {
+		self.read(0x14D)
+	}
+
+	pub fn global_checksum(&self) -> u16 {
+		combine!(self.read(0x14E), self.read(0x14F))
+	}
+
 	pub fn name(&self) -> String {
 		let mut name = String::new();
 		for index in 0x134..0x144 {
@@ -81,6 +126,16 @@ impl Rom {
 
 }
 
+impl Stateful for Rom {
+	fn save_state(&self, state: &mut StateWriter) {
+		self.controller.save_state(state);
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		self.controller.load_state(state)
+	}
+}
+
 impl fmt::Display for Rom {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f,
@@ -0,0 +1,41 @@
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
+use core::serial::*;
+
+// One end of an in-process link cable. Both ends may live on different threads.
+pub struct CableEnd {
+    sender: Sender<LinkMessage>,
+    receiver: Receiver<LinkMessage>,
+    connected: bool,
+}
+
+// Creates both ends of a cable connecting two machines in the same process
+pub fn link_cable() -> (CableEnd, CableEnd) {
+    let (a_sender, b_receiver) = channel();
+    let (b_sender, a_receiver) = channel();
+    let a = CableEnd { sender: a_sender, receiver: a_receiver, connected: true };
+    let b = CableEnd { sender: b_sender, receiver: b_receiver, connected: true };
+    (a, b)
+}
+
+impl LinkPort for CableEnd {
+    fn send(&mut self, message: LinkMessage) {
+        if self.sender.send(message).is_err() {
+            self.connected = false;
+        }
+    }
+
+    fn receive(&mut self) -> Option<LinkMessage> {
+        match self.receiver.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.connected = false;
+                None
+            },
+        }
+    }
+
+    fn connected(&self) -> bool {
+        self.connected
+    }
+}
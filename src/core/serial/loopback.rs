@@ -0,0 +1,28 @@
+use std::collections::VecDeque;
+use core::serial::*;
+
+// A plug connecting serial out straight back to serial in,
+// every byte sent with the internal clock is received again.
+pub struct Loopback {
+    queue: VecDeque<LinkMessage>,
+}
+
+impl Loopback {
+    pub fn new() -> Loopback {
+        Loopback {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl LinkPort for Loopback {
+    fn send(&mut self, message: LinkMessage) {
+        if let LinkMessage::Transfer(data) = message {
+            self.queue.push_back(LinkMessage::Reply(data));
+        }
+    }
+
+    fn receive(&mut self) -> Option<LinkMessage> {
+        self.queue.pop_front()
+    }
+}
@@ -0,0 +1,311 @@
+pub mod loopback;
+pub mod cable;
+pub mod socket;
+
+use core::helper::*;
+use core::interrupt::*;
+use core::memory_map::*;
+use core::state::*;
+
+// Serial transfers shift 8 bits at 8192Hz with the internal clock,
+// CGB fast mode shifts at 262144Hz.
+const NORMAL_CYCLES_PER_BIT: usize = 512;
+const FAST_CYCLES_PER_BIT: usize = 16;
+// How often the link port is checked for incoming data
+const POLL_CYCLES: usize = 512;
+
+// What goes over a link cable. The side with the internal clock sends a Transfer
+// with its byte, the other side answers with a Reply holding the byte it shifted out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkMessage {
+    Transfer(u8),
+    Reply(u8),
+}
+
+// Transport for the link cable between two machines
+pub trait LinkPort {
+    fn send(&mut self, message: LinkMessage);
+
+    // Non-blocking, returns None if nothing has arrived yet
+    fn receive(&mut self) -> Option<LinkMessage>;
+
+    // False once the other side has gone away, pending transfers then read 0xFF
+    fn connected(&self) -> bool {
+        true
+    }
+}
+
+pub struct Serial {
+    SB: MemoryRegister,
+    SC: MemoryRegister,
+    port: Option<Box<LinkPort>>,
+    cgb_mode: bool,
+    // Cycles left until the bits of an internally clocked transfer have been shifted out
+    transfer_cycles: usize,
+    // The transfer has been clocked out and is waiting for the other side to reply
+    awaiting_reply: bool,
+    // Byte received from the other side during the current transfer
+    reply: Option<u8>,
+    poll_cycles: usize,
+}
+
+impl Serial {
+    pub fn new(cgb_mode: bool) -> Serial {
+        Serial {
+            SB: MemoryRegister::new(0x00),
+            SC: MemoryRegister::new(0x00),
+            port: None,
+            cgb_mode: cgb_mode,
+            transfer_cycles: 0,
+            awaiting_reply: false,
+            reply: None,
+            poll_cycles: 0,
+        }
+    }
+
+    pub fn set_link_port(&mut self, port: Box<LinkPort>) {
+        self.port = Some(port);
+    }
+
+    pub fn disconnect(&mut self) {
+        self.port = None;
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            SB => self.SB.get(),
+            // Unused bits read as 1, bit 1 only exists on CGB
+            SC => match self.cgb_mode {
+                true => self.SC.get() | 0x7C,
+                false => self.SC.get() | 0x7E,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, data: u8) {
+        match address {
+            SB => self.SB.set(data),
+            SC => {
+                self.SC.set(data & 0x83);
+                if self.transfer_requested() && self.internal_clock() {
+                    self.start_transfer();
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn transfer_requested(&self) -> bool {
+        self.SC.is_set(Bit::Bit7)
+    }
+
+    fn internal_clock(&self) -> bool {
+        self.SC.is_set(Bit::Bit0)
+    }
+
+    fn start_transfer(&mut self) {
+        let cycles_per_bit = match self.cgb_mode && self.SC.is_set(Bit::Bit1) {
+            true => FAST_CYCLES_PER_BIT,
+            false => NORMAL_CYCLES_PER_BIT,
+        };
+        self.transfer_cycles = cycles_per_bit * 8;
+        self.awaiting_reply = false;
+        self.reply = None;
+        let data = self.SB.get();
+        if let Some(ref mut port) = self.port {
+            port.send(LinkMessage::Transfer(data));
+        }
+    }
+
+    fn finish_transfer(&mut self, data: u8, interrupt: &mut InterruptHandler) {
+        self.SB.set(data);
+        self.SC.clear_bit(Bit::Bit7);
+        self.awaiting_reply = false;
+        self.reply = None;
+        interrupt.request_interrupt(InterruptFlag::Serial);
+    }
+
+    pub fn cycles(&mut self, cycles: usize, interrupt: &mut InterruptHandler) {
+        if self.transfer_requested() && self.internal_clock() && !self.awaiting_reply {
+            self.transfer_cycles = self.transfer_cycles.saturating_sub(cycles);
+            if self.transfer_cycles == 0 {
+                self.awaiting_reply = true;
+            }
+        }
+
+        // Without anything plugged in, the line is pulled high and we receive 0xFF
+        let connected = match self.port {
+            Some(ref port) => port.connected(),
+            None => false,
+        };
+        if !connected {
+            if self.awaiting_reply {
+                self.finish_transfer(0xFF, interrupt);
+            }
+            return;
+        }
+
+        self.poll_cycles += cycles;
+        if self.poll_cycles < POLL_CYCLES && !self.awaiting_reply {
+            return;
+        }
+        self.poll_cycles = 0;
+
+        loop {
+            let message = match self.port {
+                Some(ref mut port) => port.receive(),
+                None => None,
+            };
+            match message {
+                // The other side clocked a byte in, shift ours out in exchange
+                Some(LinkMessage::Transfer(data)) => {
+                    let reply = self.SB.get();
+                    if let Some(ref mut port) = self.port {
+                        port.send(LinkMessage::Reply(reply));
+                    }
+                    if self.transfer_requested() && !self.internal_clock() {
+                        self.finish_transfer(data, interrupt);
+                    }
+                },
+                Some(LinkMessage::Reply(data)) => {
+                    if self.transfer_requested() && self.internal_clock() {
+                        self.reply = Some(data);
+                    }
+                },
+                None => break,
+            }
+        }
+
+        // A reply arriving early still completes once all bits have been shifted
+        if self.awaiting_reply {
+            if let Some(data) = self.reply {
+                self.finish_transfer(data, interrupt);
+            }
+        }
+    }
+}
+
+impl Stateful for Serial {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.SB.save_state(state);
+        self.SC.save_state(state);
+        state.write_usize(self.transfer_cycles);
+        state.write_bool(self.awaiting_reply);
+        state.write_bool(self.reply.is_some());
+        state.write_u8(self.reply.unwrap_or(0xFF));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.SB.load_state(state)?;
+        self.SC.load_state(state)?;
+        self.transfer_cycles = state.read_usize()?;
+        self.awaiting_reply = state.read_bool()?;
+        let has_reply = state.read_bool()?;
+        let reply = state.read_u8()?;
+        self.reply = match has_reply {
+            true => Some(reply),
+            false => None,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+    use core::serial::*;
+    use core::serial::cable::*;
+    use core::serial::socket::*;
+
+    // Clocks a byte from the first machine to the second and returns what each side received
+    fn exchange(master_port: Box<LinkPort>, slave_port: Box<LinkPort>) -> (u8, u8) {
+        let mut master = Serial::new(false);
+        let mut slave = Serial::new(false);
+        let mut master_interrupt = InterruptHandler::new();
+        let mut slave_interrupt = InterruptHandler::new();
+        master.set_link_port(master_port);
+        slave.set_link_port(slave_port);
+
+        master.write(SB, 0x42);
+        slave.write(SB, 0x99);
+        slave.write(SC, 0x80);
+        master.write(SC, 0x81);
+
+        let serial = InterruptFlag::Serial as u8;
+        let start = Instant::now();
+        while (master_interrupt.IF.get() & serial) == 0 || (slave_interrupt.IF.get() & serial) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "Transfer never finished");
+            master.cycles(16, &mut master_interrupt);
+            slave.cycles(16, &mut slave_interrupt);
+        }
+        (master.read(SB), slave.read(SB))
+    }
+
+    #[test]
+    fn cable_exchanges_bytes() {
+        let (a, b) = link_cable();
+        assert_eq!(exchange(Box::new(a), Box::new(b)), (0x99, 0x42));
+    }
+
+    #[test]
+    fn socket_exchanges_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        for stream in [&client, &server].iter() {
+            stream.set_nodelay(true).unwrap();
+            stream.set_nonblocking(true).unwrap();
+        }
+        let master = SocketLink::new(client);
+        let slave = SocketLink::new(server);
+        assert_eq!(exchange(Box::new(master), Box::new(slave)), (0x99, 0x42));
+    }
+
+    // Takes one byte per call to write, then blocks until the next call
+    struct SlowStream {
+        written: Rc<RefCell<Vec<u8>>>,
+        blocked: bool,
+    }
+
+    impl Read for SlowStream {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "Nothing to read"))
+        }
+    }
+
+    impl Write for SlowStream {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.blocked = !self.blocked;
+            match self.blocked {
+                true => {
+                    self.written.borrow_mut().push(bytes[0]);
+                    Ok(1)
+                },
+                false => Err(io::Error::new(io::ErrorKind::WouldBlock, "Socket is full")),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn socket_sends_the_rest_later() {
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let mut link = SocketLink::new(SlowStream { written: written.clone(), blocked: false });
+        link.send(LinkMessage::Transfer(0x42));
+        link.send(LinkMessage::Reply(0x99));
+        for _ in 0..4 {
+            assert!(link.receive().is_none());
+        }
+        assert!(link.connected());
+        assert_eq!(*written.borrow(), vec![0x01, 0x42, 0x02, 0x99]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use core::serial::*;
+
+// Link cable over a socket, each message is sent as two bytes: kind and data
+const TRANSFER: u8 = 0x01;
+const REPLY: u8 = 0x02;
+
+pub struct SocketLink<S: Read + Write> {
+    stream: S,
+    buffer: Vec<u8>,
+    // Bytes the stream didn't take yet, sent before anything else
+    outgoing: Vec<u8>,
+    connected: bool,
+}
+
+impl SocketLink<TcpStream> {
+    pub fn connect_tcp<A: ToSocketAddrs>(address: A) -> io::Result<SocketLink<TcpStream>> {
+        let stream = TcpStream::connect(address)?;
+        SocketLink::from_tcp(stream)
+    }
+
+    // Waits for the other side to connect
+    pub fn listen_tcp<A: ToSocketAddrs>(address: A) -> io::Result<SocketLink<TcpStream>> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        SocketLink::from_tcp(stream)
+    }
+
+    fn from_tcp(stream: TcpStream) -> io::Result<SocketLink<TcpStream>> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(SocketLink::new(stream))
+    }
+}
+
+#[cfg(unix)]
+impl SocketLink<UnixStream> {
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> io::Result<SocketLink<UnixStream>> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_nonblocking(true)?;
+        Ok(SocketLink::new(stream))
+    }
+
+    // Waits for the other side to connect
+    pub fn listen_unix<P: AsRef<Path>>(path: P) -> io::Result<SocketLink<UnixStream>> {
+        let listener = UnixListener::bind(path)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nonblocking(true)?;
+        Ok(SocketLink::new(stream))
+    }
+}
+
+impl<S: Read + Write> SocketLink<S> {
+    // The stream should be non-blocking, otherwise receiving stalls the emulator
+    pub fn new(stream: S) -> SocketLink<S> {
+        SocketLink {
+            stream: stream,
+            buffer: Vec::new(),
+            outgoing: Vec::new(),
+            connected: true,
+        }
+    }
+
+    fn fill_buffer(&mut self) {
+        let mut bytes = [0; 64];
+        loop {
+            match self.stream.read(&mut bytes) {
+                Ok(0) => {
+                    self.connected = false;
+                    return;
+                },
+                Ok(n) => self.buffer.extend_from_slice(&bytes[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(_) => {
+                    self.connected = false;
+                    return;
+                },
+            }
+        }
+    }
+
+    // Writes as much of the outgoing bytes as the stream takes without blocking
+    fn flush_outgoing(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => {
+                    self.connected = false;
+                    return;
+                },
+                Ok(n) => { self.outgoing.drain(..n); },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(_) => {
+                    self.connected = false;
+                    return;
+                },
+            }
+        }
+        match self.stream.flush() {
+            Err(ref e) if e.kind() != io::ErrorKind::WouldBlock => self.connected = false,
+            _ => {},
+        }
+    }
+}
+
+impl<S: Read + Write> LinkPort for SocketLink<S> {
+    fn send(&mut self, message: LinkMessage) {
+        let bytes = match message {
+            LinkMessage::Transfer(data) => [TRANSFER, data],
+            LinkMessage::Reply(data) => [REPLY, data],
+        };
+        if !self.connected {
+            return;
+        }
+        // A full socket only means the rest goes out on a later call
+        self.outgoing.extend_from_slice(&bytes);
+        self.flush_outgoing();
+    }
+
+    fn receive(&mut self) -> Option<LinkMessage> {
+        if self.connected {
+            self.flush_outgoing();
+        }
+        if self.buffer.len() < 2 && self.connected {
+            self.fill_buffer();
+        }
+        if self.buffer.len() < 2 {
+            return None;
+        }
+        let message: Vec<u8> = self.buffer.drain(..2).collect();
+        match message[0] {
+            TRANSFER => Some(LinkMessage::Transfer(message[1])),
+            REPLY => Some(LinkMessage::Reply(message[1])),
+            _ => {
+                // Garbage on the line, drop the connection rather than guess
+                self.connected = false;
+                None
+            },
+        }
+    }
+
+    fn connected(&self) -> bool {
+        self.connected
+    }
+}
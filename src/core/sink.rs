@@ -11,7 +11,41 @@ impl VideoSink {
 	pub fn consume(self) -> Option<Vec<u32>> {
 		self.inner
 	}
+	pub fn take(&mut self) -> Option<Vec<u32>> {
+		self.inner.take()
+	}
+	pub fn has_frame(&self) -> bool {
+		self.inner.is_some()
+	}
 	pub fn append(&mut self, value: Vec<u32>) {
 		self.inner = Some(value);
 	}
 }
+
+// Interleaved stereo samples (left, right) produced by the APU
+pub struct AudioSink {
+	inner: Vec<i16>
+}
+
+impl AudioSink {
+	pub fn new() -> AudioSink {
+		AudioSink {
+			inner: Vec::new()
+		}
+	}
+	pub fn consume(self) -> Vec<i16> {
+		self.inner
+	}
+	// Number of stereo samples collected so far
+	pub fn len(&self) -> usize {
+		self.inner.len() / 2
+	}
+	pub fn append(&mut self, left: i16, right: i16) {
+		self.inner.push(left);
+		self.inner.push(right);
+	}
+	// Moves all samples from other to the end of this sink
+	pub fn append_from(&mut self, other: &mut AudioSink) {
+		self.inner.append(&mut other.inner);
+	}
+}
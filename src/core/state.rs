@@ -0,0 +1,174 @@
+use std::fmt;
+use core::helper::*;
+
+// Save states are a flat little-endian byte stream. Every component writes its
+// fields in a fixed order and reads them back in the same order.
+
+pub const STATE_MAGIC: &'static [u8; 4] = b"GBCS";
+pub const STATE_VERSION: u8 = 13;
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+	InvalidHeader,
+	UnsupportedVersion(u8),
+	RomMismatch,
+	UnexpectedEnd,
+	InvalidValue,
+}
+
+impl fmt::Display for StateError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			StateError::InvalidHeader => write!(f, "Not a Gameboy Crust save state"),
+			StateError::UnsupportedVersion(v) => write!(f, "Unsupported save state version: {}", v),
+			StateError::RomMismatch => write!(f, "Save state belongs to a different ROM"),
+			StateError::UnexpectedEnd => write!(f, "Save state data ends unexpectedly"),
+			StateError::InvalidValue => write!(f, "Save state contains an invalid value"),
+		}
+	}
+}
+
+// Implemented by every piece of hardware that has state worth saving
+pub trait Stateful {
+	fn save_state(&self, state: &mut StateWriter);
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+pub struct StateWriter {
+	bytes: Vec<u8>
+}
+
+impl StateWriter {
+	pub fn new() -> StateWriter {
+		StateWriter {
+			bytes: Vec::new()
+		}
+	}
+
+	pub fn consume(self) -> Vec<u8> {
+		self.bytes
+	}
+
+	pub fn write_u8(&mut self, value: u8) {
+		self.bytes.push(value);
+	}
+
+	pub fn write_bool(&mut self, value: bool) {
+		self.write_u8(value as u8);
+	}
+
+	pub fn write_u16(&mut self, value: u16) {
+		self.write_u8(low!(value));
+		self.write_u8(high!(value));
+	}
+
+	pub fn write_u32(&mut self, value: u32) {
+		self.write_u16((value & 0xFFFF) as u16);
+		self.write_u16((value >> 16) as u16);
+	}
+
+	pub fn write_i32(&mut self, value: i32) {
+		self.write_u32(value as u32);
+	}
+
+	pub fn write_u64(&mut self, value: u64) {
+		self.write_u32((value & 0xFFFFFFFF) as u32);
+		self.write_u32((value >> 32) as u32);
+	}
+
+	pub fn write_usize(&mut self, value: usize) {
+		self.write_u64(value as u64);
+	}
+
+	// Variable length data is prefixed with its length
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.write_u32(bytes.len() as u32);
+		self.bytes.extend_from_slice(bytes);
+	}
+}
+
+pub struct StateReader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> StateReader<'a> {
+	pub fn new(bytes: &'a [u8]) -> StateReader<'a> {
+		StateReader {
+			bytes: bytes,
+			position: 0,
+		}
+	}
+
+	pub fn read_u8(&mut self) -> Result<u8, StateError> {
+		match self.bytes.get(self.position) {
+			Some(&value) => {
+				self.position += 1;
+				Ok(value)
+			},
+			None => Err(StateError::UnexpectedEnd),
+		}
+	}
+
+	pub fn read_bool(&mut self) -> Result<bool, StateError> {
+		Ok(self.read_u8()? != 0)
+	}
+
+	pub fn read_u16(&mut self) -> Result<u16, StateError> {
+		let low = self.read_u8()?;
+		let high = self.read_u8()?;
+		Ok(combine!(high, low))
+	}
+
+	pub fn read_u32(&mut self) -> Result<u32, StateError> {
+		let low = self.read_u16()? as u32;
+		let high = self.read_u16()? as u32;
+		Ok((high << 16) | low)
+	}
+
+	pub fn read_i32(&mut self) -> Result<i32, StateError> {
+		Ok(self.read_u32()? as i32)
+	}
+
+	pub fn read_u64(&mut self) -> Result<u64, StateError> {
+		let low = self.read_u32()? as u64;
+		let high = self.read_u32()? as u64;
+		Ok((high << 32) | low)
+	}
+
+	pub fn read_usize(&mut self) -> Result<usize, StateError> {
+		Ok(self.read_u64()? as usize)
+	}
+
+	pub fn read_bytes(&mut self) -> Result<Vec<u8>, StateError> {
+		let length = self.read_u32()? as usize;
+		if self.position + length > self.bytes.len() {
+			return Err(StateError::UnexpectedEnd);
+		}
+		let bytes = self.bytes[self.position..self.position + length].to_vec();
+		self.position += length;
+		Ok(bytes)
+	}
+
+	// Reads variable length data that must match the size of an existing buffer
+	pub fn read_into(&mut self, buffer: &mut Vec<u8>) -> Result<(), StateError> {
+		let bytes = self.read_bytes()?;
+		if bytes.len() != buffer.len() {
+			return Err(StateError::InvalidValue);
+		}
+		*buffer = bytes;
+		Ok(())
+	}
+}
+
+impl Stateful for MemoryRegister {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_u8(self.get());
+	}
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		let value = state.read_u8()?;
+		self.set(value);
+		Ok(())
+	}
+}
@@ -1,92 +1,72 @@
 use core::helper::*;
 use core::interrupt::*;
+use core::state::*;
 
-// TODO: update clock speed to reflect emulated speed
-const CLOCK_SPEED: i32 = 4194304;
+// The timer is driven by a 16-bit counter that increases every clock cycle, DIV is its upper byte.
+// TIMA increases whenever the counter bit selected by TAC, ANDed with the enable bit, goes
+// from 1 to 0. Since writes to DIV and TAC can also make that signal fall, they can increase
+// TIMA as well, which is what timing test ROMs look for.
 
-#[derive(Eq, PartialEq, Copy, Clone)]
-enum TimerFrequency {
-    Mode0 = 4096, // Hz..
-    Mode1 = 262144,
-    Mode2 = 65536,
-    Mode3 = 16384,
-}
+// Counter bit that clocks TIMA for each TAC frequency: 4096Hz, 262144Hz, 65536Hz, 16384Hz
+const TIMA_BITS: [u16; 4] = [1 << 9, 1 << 3, 1 << 5, 1 << 7];
 
 pub struct Timer {
-    DIV:  MemoryRegister,
+    counter: u16,
     TIMA: MemoryRegister,
     TMA:  MemoryRegister,
     TAC:  MemoryRegister,
-    divider_counter: i32,
-    timer_counter: i32,
-    frequency: TimerFrequency,
+    // TIMA overflowed and reads 0x00 for one machine cycle before TMA is loaded
+    overflow: bool,
+    // TMA was loaded into TIMA in the current machine cycle, writes to TIMA are ignored
+    reloaded: bool,
 }
 
 impl Timer {
     pub fn new() -> Timer {
         Timer {
-            DIV:  MemoryRegister::new(0x00),
+            counter: 0,
             TIMA: MemoryRegister::new(0x00),
             TMA:  MemoryRegister::new(0x00),
             TAC:  MemoryRegister::new(0x00),
-            divider_counter: 0,
-            timer_counter: 0,
-            frequency: TimerFrequency::Mode0
+            overflow: false,
+            reloaded: false,
         }
     }
 
+    // Cycles come in steps of one machine cycle, 4 clock cycles
     pub fn cycles(&mut self, cycles: usize, interrupt: &mut InterruptHandler) {
-        self.update_divider(cycles);
-
-        let new_freq = self.get_frequency();
-
-        if self.frequency != new_freq {
-            self.set_frequency(new_freq);
-        }
-
-        if self.timer_enabled() {
-            self.timer_counter -= cycles as i32;
-
-            if self.timer_counter <= 0 {
-                let timer_value = self.TIMA.get();
-                self.set_frequency(new_freq);
-
-                // If TIMA overflows
-                if (timer_value == 255) {
-                    self.TIMA.set(self.TMA.get());
-                    interrupt.request_interrupt(InterruptFlag::Timer);
-                } else {
-                    self.TIMA.set(timer_value + 1);
-                }
-            }
+        for _ in 0..cycles / 4 {
+            self.machine_cycle(interrupt);
         }
     }
 
-    fn set_frequency(&mut self, frequency: TimerFrequency) {
-        self.frequency = frequency;
-        self.timer_counter = CLOCK_SPEED / (frequency as i32);
-    }
+    fn machine_cycle(&mut self, interrupt: &mut InterruptHandler) {
+        self.reloaded = false;
+        if self.overflow {
+            self.overflow = false;
+            self.reloaded = true;
+            self.TIMA.set(self.TMA.get());
+            interrupt.request_interrupt(InterruptFlag::Timer);
+        }
 
-    fn get_frequency(&self) -> TimerFrequency {
-        let mode = self.TAC.get() & 3;
-        match mode {
-            0 => TimerFrequency::Mode0,
-            1 => TimerFrequency::Mode1,
-            2 => TimerFrequency::Mode2,
-            3 => TimerFrequency::Mode3,
-            _ => unreachable!(),
+        let signal = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        if signal && !self.signal() {
+            self.increment();
         }
     }
 
-    // Divider register is incremented at 16384Hz
-    // Cycles per update = Clock speed / 16384 = 256
-    // TODO: register is updated at 32768Hz in CGB mode
-    fn update_divider(&mut self, cycles: usize) {
-        self.divider_counter += cycles as i32;
+    // The input to TIMA's falling edge detector
+    fn signal(&self) -> bool {
+        let bit = TIMA_BITS[(self.TAC.get() & 3) as usize];
+        self.timer_enabled() && self.counter & bit != 0
+    }
 
-        if self.divider_counter >= 256 {
-            self.DIV.add(1);
-            self.divider_counter = 0;
+    fn increment(&mut self) {
+        let (value, overflow) = self.TIMA.get().overflowing_add(1);
+        self.TIMA.set(value);
+        if overflow {
+            self.overflow = true;
         }
     }
 
@@ -95,12 +75,21 @@ impl Timer {
     }
 
     pub fn read_div(&self) -> u8 {
-        self.DIV.get()
+        (self.counter >> 8) as u8
     }
 
-    pub fn write_div(&mut self, data: u8) {
-        // writing to div resets it
-        self.DIV.clear();
+    // Used to set up the state left behind by the boot ROM
+    pub fn set_div(&mut self, data: u8) {
+        self.counter = (data as u16) << 8;
+    }
+
+    pub fn write_div(&mut self, _data: u8) {
+        // writing to div resets the whole counter, which can be a falling edge for TIMA
+        let signal = self.signal();
+        self.counter = 0;
+        if signal {
+            self.increment();
+        }
     }
 
     pub fn read_counter(&self) -> u8 {
@@ -108,6 +97,11 @@ impl Timer {
     }
 
     pub fn write_counter(&mut self, data: u8) {
+        if self.reloaded {
+            return;
+        }
+        // Writing during the cycle after an overflow cancels the reload and the interrupt
+        self.overflow = false;
         self.TIMA.set(data);
     }
 
@@ -117,6 +111,9 @@ impl Timer {
 
     pub fn write_modulo(&mut self, data: u8) {
         self.TMA.set(data);
+        if self.reloaded {
+            self.TIMA.set(data);
+        }
     }
 
     pub fn read_control(&self) -> u8 {
@@ -124,6 +121,32 @@ impl Timer {
     }
 
     pub fn write_control(&mut self, data: u8) {
-        self.TAC.set(data);
+        // Disabling the timer or picking another bit can also make the signal fall
+        let signal = self.signal();
+        self.TAC.set(data & 0x07);
+        if signal && !self.signal() {
+            self.increment();
+        }
+    }
+}
+
+impl Stateful for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter);
+        self.TIMA.save_state(state);
+        self.TMA.save_state(state);
+        self.TAC.save_state(state);
+        state.write_bool(self.overflow);
+        state.write_bool(self.reloaded);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.counter = state.read_u16()?;
+        self.TIMA.load_state(state)?;
+        self.TMA.load_state(state)?;
+        self.TAC.load_state(state)?;
+        self.overflow = state.read_bool()?;
+        self.reloaded = state.read_bool()?;
+        Ok(())
     }
 }
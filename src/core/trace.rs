@@ -0,0 +1,152 @@
+use std::io;
+use std::io::{Write, BufWriter};
+use std::fs::File;
+use std::path::Path;
+use std::thread;
+use std::collections::VecDeque;
+use core::cpu::*;
+use core::interconnect::*;
+
+// Logs every instruction before it executes, one line each:
+// A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02 LY:00 CYC:0
+// Everything up to PCMEM is the format Gameboy Doctor and several other emulators log,
+// so traces can be compared after cutting the last two columns.
+
+enum TraceOutput {
+	// Keeps the last lines in memory, written to stderr if the emulator panics
+	Ring(VecDeque<String>, usize),
+	Writer(Box<Write>),
+}
+
+pub struct Tracer {
+	output: TraceOutput,
+	// Logging begins when PC reaches start and ends for good when it reaches stop
+	start: Option<u16>,
+	stop: Option<u16>,
+	active: bool,
+	// Cycles the machine has run for since tracing was set up
+	cycles: usize,
+}
+
+impl Tracer {
+	fn new(output: TraceOutput) -> Tracer {
+		Tracer {
+			output: output,
+			start: None,
+			stop: None,
+			active: true,
+			cycles: 0,
+		}
+	}
+
+	// Remembers only the last capacity instructions. The buffer grows as lines come in,
+	// so a huge capacity doesn't allocate anything up front.
+	pub fn ring(capacity: usize) -> Tracer {
+		Tracer::new(TraceOutput::Ring(VecDeque::new(), capacity))
+	}
+
+	pub fn to_writer<W: Write + 'static>(writer: W) -> Tracer {
+		Tracer::new(TraceOutput::Writer(Box::new(writer)))
+	}
+
+	pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+		let file = File::create(path)?;
+		Ok(Tracer::to_writer(BufWriter::new(file)))
+	}
+
+	// Waits for PC to reach address before logging anything
+	pub fn start_at(mut self, address: u16) -> Tracer {
+		self.start = Some(address);
+		self.active = false;
+		self
+	}
+
+	// Stops logging once PC reaches address, the instruction there is not logged
+	pub fn stop_at(mut self, address: u16) -> Tracer {
+		self.stop = Some(address);
+		self
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.active
+	}
+
+	// Called before the CPU executes the instruction at PC
+	pub fn trace(&mut self, cpu: &CPU, memory: &Interconnect) {
+		let pc = cpu.regs.pc;
+		if self.active && self.stop == Some(pc) {
+			self.active = false;
+			self.flush();
+			return;
+		}
+		if self.start == Some(pc) {
+			self.active = true;
+			self.start = None;
+		}
+		if !self.active {
+			return;
+		}
+
+		let line = format_line(cpu, memory, self.cycles);
+		match self.output {
+			TraceOutput::Ring(ref mut lines, capacity) => {
+				if capacity == 0 {
+					return;
+				}
+				if lines.len() == capacity {
+					lines.pop_front();
+				}
+				lines.push_back(line);
+			},
+			TraceOutput::Writer(ref mut writer) => {
+				// A trace with holes in it is useless, give up on the first error
+				if writeln!(writer, "{}", line).is_err() {
+					self.active = false;
+					self.start = None;
+				}
+			},
+		}
+	}
+
+	// Called after each instruction with the cycles it took
+	pub fn add_cycles(&mut self, cycles: usize) {
+		self.cycles += cycles;
+	}
+
+	// Writes out the lines kept in ring buffer mode, oldest first
+	pub fn dump<W: Write>(&self, output: &mut W) -> io::Result<()> {
+		if let TraceOutput::Ring(ref lines, _) = self.output {
+			for line in lines {
+				writeln!(output, "{}", line)?;
+			}
+		}
+		Ok(())
+	}
+
+	pub fn flush(&mut self) {
+		if let TraceOutput::Writer(ref mut writer) = self.output {
+			let _ = writer.flush();
+		}
+	}
+}
+
+impl Drop for Tracer {
+	fn drop(&mut self) {
+		self.flush();
+		if thread::panicking() {
+			let stderr = io::stderr();
+			let mut stderr = stderr.lock();
+			let _ = writeln!(stderr, "Last instructions before the panic:");
+			let _ = self.dump(&mut stderr);
+		}
+	}
+}
+
+fn format_line(cpu: &CPU, memory: &Interconnect, cycles: usize) -> String {
+	let regs = &cpu.regs;
+	let pc = regs.pc;
+	format!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X} LY:{:02X} CYC:{}",
+		regs.a, regs.f, regs.b, regs.c, regs.d, regs.e, regs.h, regs.l, regs.sp, pc,
+		memory.peek(pc), memory.peek(pc.wrapping_add(1)), memory.peek(pc.wrapping_add(2)), memory.peek(pc.wrapping_add(3)),
+		memory.gpu.LY.get(), cycles)
+}
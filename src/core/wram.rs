@@ -1,26 +1,71 @@
+use core::state::*;
+
 // Each bank is 4KB, 2 banks in GB, 8 banks in CGB, 32KB Total
 pub const WRAM_SIZE: usize = 32768;
 
+pub const WRAM_BANK_SIZE: usize = 4096;
+
 pub struct Wram {
-	bytes: Vec<u8>
+	bytes: Vec<u8>,
+	// Bank mapped to 0xD000-0xDFFF, selected with SVBK in CGB mode
+	bank: u8,
 }
 
 impl Wram {
 	pub fn new() -> Wram {
 		Wram {
-			bytes: vec![0; WRAM_SIZE]
+			bytes: vec![0; WRAM_SIZE],
+			bank: 1,
+		}
+	}
+
+	// Translate an offset into WRAM to its location in the currently switched bank
+	#[inline]
+	fn index(&self, address: u16) -> usize {
+		let address = address as usize;
+		match address < WRAM_BANK_SIZE {
+			true => address,
+			false => (self.bank as usize * WRAM_BANK_SIZE) + (address - WRAM_BANK_SIZE),
 		}
 	}
 
+	pub fn read_bank(&self) -> u8 {
+		self.bank
+	}
+
+	// Writing bank 0 selects bank 1
+	pub fn write_bank(&mut self, data: u8) {
+		self.bank = match data & 0x07 {
+			0 => 1,
+			n => n,
+		};
+	}
+
 	pub fn size(&self) -> usize {
 		self.bytes.len()
 	}
 
 	pub fn read(&self, address: u16) -> u8 {
-		self.bytes[address as usize]
+		self.bytes[self.index(address)]
 	}
 
 	pub fn write(&mut self, address: u16, data: u8) {
-		self.bytes[address as usize] = data;
+		let index = self.index(address);
+		self.bytes[index] = data;
+	}
+}
+
+impl Stateful for Wram {
+	fn save_state(&self, state: &mut StateWriter) {
+		state.write_bytes(&self.bytes);
+		state.write_u8(self.bank);
 	}
-}
\ No newline at end of file
+
+	fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+		state.read_into(&mut self.bytes)?;
+		match state.read_u8()? {
+			bank @ 1 ... 7 => { self.bank = bank; Ok(()) },
+			_ => Err(StateError::InvalidValue),
+		}
+	}
+}
@@ -2,8 +2,18 @@ use core::gameboy::*;
 use core::rom::*;
 use core::sink::*;
 use core::joypad::*;
+use core::serial::*;
+use core::boot::*;
+use core::debugger::*;
+use core::debugger::console::*;
+use core::trace::*;
+use core::gpu::*;
+use core::palette::*;
 use minifb::{Key, WindowOptions, Window, Scale, KeyRepeat};
 use std::thread;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
 use std::time::{Duration, Instant};
 
 const CLOCK_SPEED: i32 = 4194304;
@@ -12,36 +22,86 @@ const FRAME_RATE: i32 = 60;
 pub struct Emulator {
 	gameboy: GameBoy,
 	window: Window,
+	debugger: Debugger,
+	// Open the debugger console before the next instruction
+	break_requested: bool,
+	quit: bool,
+	// Preset picked last with the palette key
+	palette_preset: usize,
+	color_correction: bool,
 }
 
 impl Emulator {
 
-	pub fn new(rom: Rom) -> Emulator {
+	pub fn new(rom: Rom, boot_rom: Option<BootRom>) -> Emulator {
 		let mut title = "Gameboy Crust - ".to_owned();
 		title.push_str(&rom.name());
 		Emulator {
-			gameboy: GameBoy::new(rom),
+			gameboy: match boot_rom {
+				Some(boot_rom) => GameBoy::with_boot_rom(rom, boot_rom),
+				None => GameBoy::new(rom),
+			},
 			window: Window::new(title.as_str(), 160, 144, WindowOptions {
 				borderless: false,
 				title: true,
 				resize: false,
 				scale: Scale::X4,
 			}).unwrap(),
+			debugger: Debugger::new(),
+			break_requested: false,
+			quit: false,
+			palette_preset: 0,
+			color_correction: false,
 		}
 	}
 
+	pub fn break_into_debugger(&mut self) {
+		self.break_requested = true;
+	}
+
+	pub fn set_link_port<P: LinkPort + 'static>(&mut self, port: P) {
+		self.gameboy.set_link_port(port);
+	}
+
+	pub fn set_tracer(&mut self, tracer: Tracer) {
+		self.gameboy.set_tracer(tracer);
+	}
+
+	pub fn set_renderer(&mut self, renderer: Renderer) {
+		self.gameboy.set_renderer(renderer);
+	}
+
+	pub fn set_palette(&mut self, palette: DmgPalette) {
+		self.gameboy.set_dmg_palette(palette);
+	}
+
+	pub fn set_color_correction(&mut self, enabled: bool) {
+		self.color_correction = enabled;
+		self.gameboy.set_color_correction(enabled);
+	}
+
+	// Switches to the next built-in palette
+	fn next_palette(&mut self) {
+		self.palette_preset = (self.palette_preset + 1) % PRESETS.len();
+		let (name, palette) = PRESETS[self.palette_preset];
+		println!("Palette: {}", name);
+		self.set_palette(palette);
+	}
+
 	pub fn run(&mut self) {
 
 		let mut tile_window: Option<Window> = None;
 		let mut multiplier = 100;
 		let mut overclock = false;
 
-		while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
+		while self.window.is_open() && !self.window.is_key_down(Key::Escape) && !self.quit {
 
 			let start_time = Instant::now();
 			let frame_time = Duration::new(0, 16600000); // 16.6 ms as nanoseconds
 
 			let mut video_sink = VideoSink::new();
+			// There is no audio device yet, samples are discarded every frame
+			let mut audio_sink = AudioSink::new();
 			let mut clock_speed = CLOCK_SPEED;
 			if overclock {
 				clock_speed *= multiplier;
@@ -49,8 +109,21 @@ impl Emulator {
 			let cycles_per_frame = clock_speed / FRAME_RATE;
 			let mut emulated_cycles = 0;
 
-			while emulated_cycles <= cycles_per_frame {
-				emulated_cycles += self.gameboy.step(&mut video_sink) as i32;
+			while emulated_cycles <= cycles_per_frame && !self.quit {
+				if self.break_requested {
+					self.debug_console();
+				}
+				let (reason, cycles) = self.debugger.step(&mut self.gameboy, &mut video_sink, &mut audio_sink);
+				emulated_cycles += cycles as i32;
+				match reason {
+					StopReason::Step => {},
+					// The CPU is gone for good, the console would only show the same error again
+					StopReason::Error(_) => println!("{}", reason),
+					_ => {
+						println!("{}", reason);
+						self.break_requested = true;
+					},
+				}
 			}
 
 			if let Some(frame) = video_sink.consume() {
@@ -61,9 +134,34 @@ impl Emulator {
 				if self.window.is_key_pressed(Key::D, KeyRepeat::No) {
 					self.debug();
 				}
+				if self.window.is_key_pressed(Key::B, KeyRepeat::No) {
+					self.break_into_debugger();
+				}
+				if self.window.is_key_pressed(Key::F5, KeyRepeat::No) {
+					self.save_state();
+				}
+				if self.window.is_key_pressed(Key::F8, KeyRepeat::No) {
+					self.load_state();
+				}
+				if self.window.is_key_pressed(Key::P, KeyRepeat::No) {
+					self.next_palette();
+				}
+				if self.window.is_key_pressed(Key::C, KeyRepeat::No) {
+					let enabled = !self.color_correction;
+					println!("Color correction {}", match enabled { true => "on", false => "off" });
+					self.set_color_correction(enabled);
+				}
 				overclock = self.window.is_key_down(Key::Space);
 				self.read_input();
 				self.vram_loop(&mut tile_window);
+			} else if self.gameboy.cpu.stopped() || self.gameboy.cpu.locked() {
+				// No frames are drawn in STOP mode, keep the window alive so a button press can wake it.
+				// A locked up CPU may have left the LCD off, the keys to quit or load a state must still work.
+				self.window.update();
+				self.read_input();
+				if self.window.is_key_pressed(Key::F8, KeyRepeat::No) {
+					self.load_state();
+				}
 			}
 
 			// We have done our calculations, wait the remaning time
@@ -78,15 +176,20 @@ impl Emulator {
 	}
 
 	fn read_input(&mut self) {
-		let i = &mut self.gameboy.interconnect.interrupt;
-		self.gameboy.interconnect.joypad.set_direction_pressed(i, PAD_UP, self.window.is_key_down(Key::Up));
-		self.gameboy.interconnect.joypad.set_direction_pressed(i, PAD_DOWN, self.window.is_key_down(Key::Down));
-		self.gameboy.interconnect.joypad.set_direction_pressed(i, PAD_LEFT, self.window.is_key_down(Key::Left));
-		self.gameboy.interconnect.joypad.set_direction_pressed(i, PAD_RIGHT, self.window.is_key_down(Key::Right));
-		self.gameboy.interconnect.joypad.set_button_pressed(i, BUTTON_A, self.window.is_key_down(Key::A));
-		self.gameboy.interconnect.joypad.set_button_pressed(i, BUTTON_B, self.window.is_key_down(Key::S));
-		self.gameboy.interconnect.joypad.set_button_pressed(i, BUTTON_START, self.window.is_key_down(Key::Z));
-		self.gameboy.interconnect.joypad.set_button_pressed(i, BUTTON_SELECT, self.window.is_key_down(Key::X));
+		let keys = [
+			(Button::Up, Key::Up),
+			(Button::Down, Key::Down),
+			(Button::Left, Key::Left),
+			(Button::Right, Key::Right),
+			(Button::A, Key::A),
+			(Button::B, Key::S),
+			(Button::Start, Key::Z),
+			(Button::Select, Key::X),
+		];
+		for &(button, key) in keys.iter() {
+			let pressed = self.window.is_key_down(key);
+			self.gameboy.set_button(button, pressed);
+		}
 	}
 
 	fn vram_loop(&mut self, window: &mut Option<Window>) {
@@ -119,6 +222,45 @@ impl Emulator {
 		}
 	}
 
+	fn state_path(&self) -> String {
+		let mut path = self.gameboy.interconnect.rom().name();
+		path.push_str(".state");
+		path
+	}
+
+	fn save_state(&self) {
+		let path = self.state_path();
+		let state = self.gameboy.save_state();
+		match File::create(&path).and_then(|mut file| file.write_all(&state)) {
+			Ok(_) => println!("Saved state to {}", path),
+			Err(e) => println!("Unable to save state: {}", e),
+		}
+	}
+
+	fn load_state(&mut self) {
+		let path = self.state_path();
+		let mut buffer = Vec::new();
+		if let Err(e) = File::open(&path).and_then(|mut file| file.read_to_end(&mut buffer)) {
+			println!("Unable to read state {}: {}", path, e);
+			return;
+		}
+		match self.gameboy.load_state(&buffer) {
+			Ok(_) => println!("Loaded state from {}", path),
+			Err(e) => println!("Unable to load state: {}", e),
+		}
+	}
+
+	// Blocks on the terminal until execution resumes
+	fn debug_console(&mut self) {
+		self.break_requested = false;
+		let stdin = io::stdin();
+		let stdout = io::stdout();
+		let action = run_console(&mut self.debugger, &mut self.gameboy, &mut stdin.lock(), &mut stdout.lock());
+		if let ConsoleAction::Quit = action {
+			self.quit = true;
+		}
+	}
+
 	fn debug(&self) {
 		self.gameboy.interconnect.gpu.dump();
 	}
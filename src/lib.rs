@@ -0,0 +1,22 @@
+// Gameboy Crust core library
+// The emulation core is fully headless and can be driven without a window.
+// The minifb front-end is only built with the "frontend" feature.
+
+#[cfg(feature = "frontend")]
+extern crate minifb;
+
+pub mod core;
+#[cfg(feature = "frontend")]
+pub mod emu;
+
+pub use core::gameboy::GameBoy;
+pub use core::rom::Rom;
+pub use core::boot::{BootRom, Model};
+pub use core::sink::{VideoSink, AudioSink};
+pub use core::joypad::Button;
+pub use core::state::StateError;
+pub use core::mbc::rtc::{RtcClock, SystemClock, ManualClock};
+pub use core::serial::{LinkPort, LinkMessage};
+pub use core::serial::loopback::Loopback;
+pub use core::serial::cable::{link_cable, CableEnd};
+pub use core::serial::socket::SocketLink;
@@ -1,20 +1,152 @@
-extern crate minifb;
-
-mod core;
-mod emu;
+extern crate gameboy_crust;
 
 use std::env;
-use core::*;
-use emu::*;
+use std::fs;
+use std::fs::File;
+use std::io;
+use gameboy_crust::core::*;
+use gameboy_crust::emu::*;
+use gameboy_crust::core::serial::socket::*;
+use gameboy_crust::core::debugger::*;
+use gameboy_crust::core::debugger::gdb::*;
+use gameboy_crust::core::debugger::expression::parse_number;
+use gameboy_crust::core::trace::*;
+use gameboy_crust::core::gpu::Renderer;
+use gameboy_crust::core::palette::DmgPalette;
 
 fn main() {
 
 	let mut args = env::args();
 	let rom_path = args.nth(1).expect("No ROM Path Given");
-	let rom = rom::Rom::load(rom_path);
+
+	// disassemble <rom> [output] writes an RGBDS listing of the ROM, to stdout by default
+	if rom_path == "disassemble" {
+		let rom_path = args.next().expect("No ROM Path Given");
+		let bytes = fs::read(&rom_path).expect("Unable to read ROM");
+		let result = match args.next() {
+			Some(path) => disassembler::rom::disassemble_rom(&bytes, &mut File::create(path).expect("Unable to create output file")),
+			None => disassembler::rom::disassemble_rom(&bytes, &mut io::stdout()),
+		};
+		result.expect("Unable to write disassembly");
+		return;
+	}
+
+	let rom = match rom::Rom::load(rom_path) {
+		Ok(rom) => rom,
+		Err(e) => {
+			println!("Unable to load ROM: {}", e);
+			return;
+		}
+	};
 
 	println!("{}", rom);
 
-	let mut emulator = emulator::Emulator::new(rom);
+	// --boot-rom <path>
+	// Link cable over TCP: --link-listen <address> or --link-connect <address>
+	// --debug starts in the debugger console
+	// --gdb <address> runs without a window, controlled by a GDB client
+	// Tracing: --trace <file> logs every instruction, --trace-ring <count> keeps the last ones
+	// to print if the emulator panics. --trace-start/--trace-stop <address> limit it to a range of PCs.
+	// --renderer scanline|fifo
+	// --palette <preset or file> for DMG games, --color-correction for CGB games
+	let mut boot_rom = None;
+	let mut link = None;
+	let mut debug = false;
+	let mut gdb = None;
+	let mut tracer = None;
+	let mut trace_start = None;
+	let mut trace_stop = None;
+	let mut renderer = None;
+	let mut palette = None;
+	let mut color_correction = false;
+	while let Some(option) = args.next() {
+		if option == "--debug" {
+			debug = true;
+			continue;
+		}
+		if option == "--color-correction" {
+			color_correction = true;
+			continue;
+		}
+		let value = args.next().expect("Missing option value");
+		match option.as_str() {
+			"--boot-rom" => boot_rom = Some(boot::BootRom::load(value).expect("Unable to load boot ROM")),
+			"--link-listen" => {
+				println!("Waiting for link cable connection on {}", value);
+				link = Some(SocketLink::listen_tcp(value.as_str()));
+			},
+			"--link-connect" => link = Some(SocketLink::connect_tcp(value.as_str())),
+			"--gdb" => gdb = Some(value),
+			"--trace" => tracer = Some(Tracer::to_file(&value).expect("Unable to create trace file")),
+			"--trace-ring" => tracer = Some(Tracer::ring(value.parse().expect("Invalid trace ring size"))),
+			"--trace-start" => trace_start = Some(parse_number(&value).expect("Invalid trace start address")),
+			"--trace-stop" => trace_stop = Some(parse_number(&value).expect("Invalid trace stop address")),
+			"--renderer" => renderer = Some(match value.as_str() {
+				"scanline" => Renderer::Scanline,
+				"fifo" => Renderer::Fifo,
+				_ => panic!("Unknown renderer: {}", value),
+			}),
+			"--palette" => palette = Some(match DmgPalette::preset(&value) {
+				Some(preset) => preset,
+				None => DmgPalette::load(&value).unwrap_or_else(|e| panic!("Unable to load palette: {}", e)),
+			}),
+			_ => panic!("Unknown option: {}", option),
+		}
+	}
+	let tracer = tracer.map(|tracer| {
+		let tracer = match trace_start {
+			Some(address) => tracer.start_at(address),
+			None => tracer,
+		};
+		match trace_stop {
+			Some(address) => tracer.stop_at(address),
+			None => tracer,
+		}
+	});
+
+	if let Some(address) = gdb {
+		let mut gameboy = match boot_rom {
+			Some(boot_rom) => gameboy::GameBoy::with_boot_rom(rom, boot_rom),
+			None => gameboy::GameBoy::new(rom),
+		};
+		if let Some(link) = link {
+			gameboy.set_link_port(link.expect("Unable to connect link cable"));
+		}
+		if let Some(tracer) = tracer {
+			gameboy.set_tracer(tracer);
+		}
+		if let Some(renderer) = renderer {
+			gameboy.set_renderer(renderer);
+		}
+		if let Some(palette) = palette {
+			gameboy.set_dmg_palette(palette);
+		}
+		gameboy.set_color_correction(color_correction);
+		println!("Waiting for GDB connection on {}", address);
+		let mut stub = GdbStub::listen(address.as_str()).expect("Unable to start GDB server");
+		if let Err(e) = stub.serve(&mut Debugger::new(), &mut gameboy) {
+			println!("GDB connection closed: {}", e);
+		}
+		return;
+	}
+
+	let mut emulator = emulator::Emulator::new(rom, boot_rom);
+	if let Some(link) = link {
+		emulator.set_link_port(link.expect("Unable to connect link cable"));
+	}
+	if let Some(tracer) = tracer {
+		emulator.set_tracer(tracer);
+	}
+	if let Some(renderer) = renderer {
+		emulator.set_renderer(renderer);
+	}
+	if let Some(palette) = palette {
+		emulator.set_palette(palette);
+	}
+	emulator.set_color_correction(color_correction);
+	if debug {
+		emulator.break_into_debugger();
+	}
+
 	emulator.run();
 }